@@ -0,0 +1,167 @@
+//! Ballots stored without the identities of the people who cast them.
+//!
+//! A voter is only ever recorded as two keyed hashes of their user id and the poll id: a token
+//! saying that they have voted, and a slot holding their current ballot. The two hashes use
+//! different labels, so the set of voters and the ballot box cannot be joined without the key,
+//! which lives outside the poll database. Recomputing the slot is what lets a voter change their
+//! own ballot, and keeps it to one ballot per person.
+
+use std::{collections::BTreeMap, env, fs, io};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+use sha2::Sha256;
+
+use crate::{verify::Opening, Ballot};
+
+pub struct VoterKey([u8; 32]);
+
+impl VoterKey {
+    #[cfg(test)]
+    pub fn new(key: [u8; 32]) -> Self {
+        VoterKey(key)
+    }
+
+    /// Read the key from `BALLOT_KEY` (hex), or else from the file at `BALLOT_KEY_PATH`
+    /// (default `ballot.key`), creating that file with a fresh key on first run.
+    ///
+    /// Losing the key unlinks voters from their ballots for good, so they could vote again.
+    pub fn load() -> io::Result<Self> {
+        let hex_key = match env::var("BALLOT_KEY") {
+            Ok(hex_key) => hex_key,
+            Err(_) => {
+                let path = env::var("BALLOT_KEY_PATH").unwrap_or_else(|_| "ballot.key".to_string());
+                match fs::read_to_string(&path) {
+                    Ok(hex_key) => hex_key,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        let mut key = [0u8; 32];
+                        rand::thread_rng().fill_bytes(&mut key);
+                        fs::write(&path, hex::encode(key))?;
+                        println!("Created a new ballot key in {}", path);
+                        return Ok(VoterKey(key));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        hex::decode(hex_key.trim())
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .map(VoterKey)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "ballot key is not 32 hex bytes")
+            })
+    }
+
+    fn hash(&self, label: &str, poll_id: &str, user: UserId) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes any key length");
+        for part in [label.as_bytes(), poll_id.as_bytes(), &user.0.to_be_bytes()] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    pub fn voter(&self, poll_id: &str, user: UserId) -> Voter {
+        Voter {
+            token: self.hash("voted", poll_id, user),
+            slot: self.hash("ballot", poll_id, user),
+        }
+    }
+}
+
+/// The keyed hashes standing in for one user in one poll.
+pub struct Voter {
+    token: String,
+    slot: String,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BallotBox {
+    /// When each voter first voted, by "has voted" token.
+    voted: BTreeMap<String, DateTime<Utc>>,
+    /// Current ballots, by slot. Ordered by hash, so neither voting order nor voter shows.
+    ballots: BTreeMap<String, Ballot>,
+    /// Commitment openings of a verifiable poll, by slot.
+    receipts: BTreeMap<String, Opening>,
+}
+
+impl BallotBox {
+    pub fn len(&self) -> usize {
+        self.ballots.len()
+    }
+
+    pub fn ballots(&self) -> impl Iterator<Item = &Ballot> {
+        self.ballots.values()
+    }
+
+    pub fn get(&self, voter: &Voter) -> Option<&Ballot> {
+        self.ballots.get(&voter.slot)
+    }
+
+    pub fn first_voted(&self, voter: &Voter) -> Option<&DateTime<Utc>> {
+        self.voted.get(&voter.token)
+    }
+
+    /// Store `voter`'s ballot, replacing any earlier one, or withdraw it when `ballot` is `None`.
+    pub fn cast(&mut self, voter: &Voter, ballot: Option<Ballot>) {
+        match ballot {
+            Some(ballot) => {
+                self.voted
+                    .entry(voter.token.clone())
+                    .or_insert_with(Utc::now);
+                self.ballots.insert(voter.slot.clone(), ballot);
+            }
+            None => {
+                self.ballots.remove(&voter.slot);
+            }
+        }
+    }
+
+    pub fn receipt(&self, voter: &Voter) -> Option<&Opening> {
+        self.receipts.get(&voter.slot)
+    }
+
+    pub fn receipts(&self) -> impl Iterator<Item = &Opening> {
+        self.receipts.values()
+    }
+
+    /// Replace `voter`'s receipt, returning the one it replaces.
+    pub fn set_receipt(&mut self, voter: &Voter, receipt: Option<Opening>) -> Option<Opening> {
+        match receipt {
+            Some(receipt) => self.receipts.insert(voter.slot.clone(), receipt),
+            None => self.receipts.remove(&voter.slot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voters_keep_one_ballot_under_unlinked_keys() {
+        let key = VoterKey([7; 32]);
+        let alice = key.voter("lunch", UserId(1));
+        let bob = key.voter("lunch", UserId(2));
+        let mut ballot_box = BallotBox::default();
+
+        ballot_box.cast(&alice, Some(Ballot::Choice("Pizza".to_string())));
+        ballot_box.cast(&bob, Some(Ballot::Choice("Soup".to_string())));
+        ballot_box.cast(&alice, Some(Ballot::Choice("Soup".to_string())));
+        assert_eq!(ballot_box.len(), 2);
+        assert!(matches!(ballot_box.get(&alice), Some(Ballot::Choice(c)) if c == "Soup"));
+
+        ballot_box.cast(&bob, None);
+        assert_eq!(ballot_box.len(), 1);
+        assert!(ballot_box.first_voted(&bob).is_some());
+
+        // Nothing stored under a voter's ballot slot says that they voted, or the other way round.
+        assert_ne!(alice.token, alice.slot);
+        assert_ne!(key.voter("dinner", UserId(1)).slot, alice.slot);
+        assert!(!ballot_box.voted.contains_key(&alice.slot));
+    }
+}
@@ -0,0 +1,238 @@
+//! Bar charts of poll results, rasterised in memory and encoded as PNG.
+//!
+//! Text is drawn with a built-in 5x7 bitmap font covering printable ASCII; anything else is
+//! drawn as `?`.
+
+const WIDTH: usize = 800;
+const SCALE: usize = 2;
+const GLYPH_WIDTH: usize = 6 * SCALE;
+const ROW_HEIGHT: usize = 32;
+const MARGIN: usize = 16;
+const LABEL_WIDTH: usize = 18 * GLYPH_WIDTH;
+const VALUE_WIDTH: usize = 20 * GLYPH_WIDTH;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const TRACK: [u8; 3] = [235, 237, 240];
+const BAR: [u8; 3] = [88, 101, 242];
+const TEXT: [u8; 3] = [35, 39, 42];
+
+/// Columns of each glyph from `' '` to `'~'`, least significant bit at the top.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x01, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x32],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x04, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x7F, 0x20, 0x18, 0x20, 0x7F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7E, 0x09, 0x01, 0x02],
+    [0x08, 0x14, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x00, 0x7F, 0x10, 0x28, 0x44],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// One bar: the option, how long its bar is relative to the chart's scale, and its caption.
+pub struct Bar {
+    pub label: String,
+    pub value: f64,
+    pub caption: String,
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: BACKGROUND.repeat(width * height),
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, colour: [u8; 3]) {
+        for row in y..(y + h).min(self.height) {
+            for col in x..(x + w).min(self.width) {
+                let i = 3 * (row * self.width + col);
+                self.pixels[i..i + 3].copy_from_slice(&colour);
+            }
+        }
+    }
+
+    /// Draw `text` from `(x, y)`, cut short with `..` if it is wider than `max_width`.
+    fn text(&mut self, x: usize, y: usize, text: &str, max_width: usize) {
+        let max_chars = max_width / GLYPH_WIDTH;
+        let mut chars: Vec<char> = text.chars().collect();
+        if chars.len() > max_chars {
+            chars.truncate(max_chars.saturating_sub(2));
+            chars.extend(['.', '.']);
+        }
+        for (i, c) in chars.into_iter().enumerate() {
+            let glyph = match c {
+                ' '..='~' => FONT[c as usize - ' ' as usize],
+                _ => FONT['?' as usize - ' ' as usize],
+            };
+            for (col, bits) in glyph.iter().enumerate() {
+                for row in 0..7 {
+                    if bits & (1 << row) != 0 {
+                        self.fill(
+                            x + i * GLYPH_WIDTH + col * SCALE,
+                            y + row * SCALE,
+                            SCALE,
+                            SCALE,
+                            TEXT,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.pixels)?;
+        }
+        Ok(png)
+    }
+}
+
+/// Render `bars` top to bottom, with a bar of length `scale` spanning the whole track.
+pub fn bar_chart(title: &str, bars: &[Bar], scale: f64) -> Result<Vec<u8>, png::EncodingError> {
+    let height = 2 * MARGIN + ROW_HEIGHT * (bars.len() + 1);
+    let mut canvas = Canvas::new(WIDTH, height);
+    canvas.text(MARGIN, MARGIN, title, WIDTH - 2 * MARGIN);
+
+    let track_x = MARGIN + LABEL_WIDTH;
+    let track_width = WIDTH - track_x - VALUE_WIDTH - MARGIN;
+    for (i, bar) in bars.iter().enumerate() {
+        let y = MARGIN + ROW_HEIGHT * (i + 1);
+        canvas.text(MARGIN, y + 9, &bar.label, LABEL_WIDTH - GLYPH_WIDTH);
+        canvas.fill(track_x, y + 4, track_width, ROW_HEIGHT - 8, TRACK);
+        let share = match scale > 0.0 {
+            true => (bar.value / scale).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        let length = (share * track_width as f64).round() as usize;
+        canvas.fill(track_x, y + 4, length, ROW_HEIGHT - 8, BAR);
+        canvas.text(
+            track_x + track_width + GLYPH_WIDTH,
+            y + 9,
+            &bar.caption,
+            VALUE_WIDTH - GLYPH_WIDTH,
+        );
+    }
+    canvas.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chart_is_a_png_with_a_row_per_bar() {
+        let bars: Vec<Bar> = [("Pizza", 3.0), ("Soup", 1.0), ("Crème brûlée", 0.0)]
+            .iter()
+            .map(|(label, value)| Bar {
+                label: label.to_string(),
+                value: *value,
+                caption: format!("{} ({:.1}%)", value, 25.0 * value),
+            })
+            .collect();
+        let png = bar_chart("Results for poll lunch", &bars, 4.0).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let height = u32::from_be_bytes(png[20..24].try_into().unwrap()) as usize;
+        assert_eq!(height, 2 * MARGIN + ROW_HEIGHT * 4);
+    }
+}
@@ -0,0 +1,124 @@
+//! The `custom_id` of every button and select menu the bot posts.
+//!
+//! Ids are `v<version>:<poll key>:<control>`, where the poll key is the poll's internal number
+//! and options are referred to by index, so no user text ever ends up in an id. That keeps them
+//! far below Discord's 100 character limit, whatever the poll id and options are.
+
+use std::{fmt, str::FromStr};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control {
+    /// The button for one option of a plurality poll.
+    Choose(usize),
+    /// The select menu used instead of buttons when a plurality poll has many options.
+    ChoosePick,
+    ApprovePick,
+    DraftStart,
+    DraftPick,
+    DraftReset,
+    DraftSubmit,
+    Withdraw,
+    Abstain,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComponentId {
+    pub poll: u64,
+    pub control: Control,
+}
+
+impl ComponentId {
+    pub fn new(poll: u64, control: Control) -> Self {
+        ComponentId { poll, control }
+    }
+}
+
+impl fmt::Display for ComponentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}:{}:", VERSION, self.poll)?;
+        match self.control {
+            Control::Choose(index) => write!(f, "o{}", index),
+            Control::ChoosePick => write!(f, "c"),
+            Control::ApprovePick => write!(f, "a"),
+            Control::DraftStart => write!(f, "ds"),
+            Control::DraftPick => write!(f, "dp"),
+            Control::DraftReset => write!(f, "dr"),
+            Control::DraftSubmit => write!(f, "dx"),
+            Control::Withdraw => write!(f, "w"),
+            Control::Abstain => write!(f, "x"),
+        }
+    }
+}
+
+impl FromStr for ComponentId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = s.split(':');
+        let (version, poll, control) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(version), Some(poll), Some(control), None) => (version, poll, control),
+                _ => return Err(format!("malformed component id {:?}", s)),
+            };
+        if version != format!("v{}", VERSION) {
+            return Err(format!("unsupported component id version in {:?}", s));
+        }
+        let poll = poll
+            .parse()
+            .map_err(|_| format!("bad poll key in component id {:?}", s))?;
+        let control = match control {
+            "c" => Control::ChoosePick,
+            "a" => Control::ApprovePick,
+            "ds" => Control::DraftStart,
+            "dp" => Control::DraftPick,
+            "dr" => Control::DraftReset,
+            "dx" => Control::DraftSubmit,
+            "w" => Control::Withdraw,
+            "x" => Control::Abstain,
+            _ => match control.strip_prefix('o').map(str::parse) {
+                Some(Ok(index)) => Control::Choose(index),
+                _ => return Err(format!("unknown control in component id {:?}", s)),
+            },
+        };
+        Ok(ComponentId { poll, control })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_round_trip_and_reject_anything_else() {
+        for control in [
+            Control::Choose(24),
+            Control::ChoosePick,
+            Control::ApprovePick,
+            Control::DraftStart,
+            Control::DraftPick,
+            Control::DraftReset,
+            Control::DraftSubmit,
+            Control::Withdraw,
+            Control::Abstain,
+        ] {
+            let id = ComponentId::new(u64::MAX, control);
+            let text = id.to_string();
+            assert!(text.len() <= 100);
+            assert_eq!(text.parse::<ComponentId>(), Ok(id));
+        }
+
+        for bad in [
+            "lunch<id:option>Pizza",
+            "v2:1:w",
+            "v1:lunch:w",
+            "v1:1:o",
+            "v1:1:o-1",
+            "v1:1:w:extra",
+            "",
+        ] {
+            assert!(bad.parse::<ComponentId>().is_err(), "{}", bad);
+        }
+    }
+}
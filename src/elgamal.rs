@@ -0,0 +1,220 @@
+//! Exponential ElGamal over the Ristretto group, with Shamir-shared secret keys.
+//!
+//! A count `m` is encrypted as `(rG, mG + rP)` for public key `P`. Adding ciphertexts adds the
+//! counts underneath, so a poll's ballots can be summed without decrypting any of them. The secret
+//! key is split among `n` trustees so that any `k` of them can decrypt a sum: each submits a share,
+//! from which a partial decryption is computed, and `k` partial decryptions combine by Lagrange
+//! interpolation. Decryption ends in a small discrete logarithm, found by counting up to the
+//! number of voters.
+
+use std::{fmt, iter::Sum, ops::Add};
+
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT as G,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A group element, serialized as the hex of its compressed form.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Point(RistrettoPoint);
+
+impl Point {
+    fn to_hex(self) -> String {
+        hex::encode(self.0.compress().to_bytes())
+    }
+
+    fn from_hex(s: &str) -> Option<Self> {
+        let bytes = hex::decode(s).ok()?;
+        CompressedRistretto::from_slice(&bytes)
+            .ok()?
+            .decompress()
+            .map(Point)
+    }
+}
+
+impl Serialize for Point {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Point {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Point::from_hex(&s).ok_or_else(|| de::Error::custom("invalid group element"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ciphertext {
+    c1: Point,
+    c2: Point,
+}
+
+impl Ciphertext {
+    fn zero() -> Self {
+        Ciphertext {
+            c1: Point(RistrettoPoint::identity()),
+            c2: Point(RistrettoPoint::identity()),
+        }
+    }
+}
+
+impl Add for Ciphertext {
+    type Output = Ciphertext;
+
+    fn add(self, other: Ciphertext) -> Ciphertext {
+        Ciphertext {
+            c1: Point(self.c1.0 + other.c1.0),
+            c2: Point(self.c2.0 + other.c2.0),
+        }
+    }
+}
+
+impl Sum for Ciphertext {
+    fn sum<I: Iterator<Item = Ciphertext>>(iter: I) -> Ciphertext {
+        iter.fold(Ciphertext::zero(), Add::add)
+    }
+}
+
+pub fn encrypt(public_key: &Point, m: u64) -> Ciphertext {
+    let r = Scalar::random(&mut rand::thread_rng());
+    Ciphertext {
+        c1: Point(r * G),
+        c2: Point(Scalar::from(m) * G + r * public_key.0),
+    }
+}
+
+/// One trustee's share of a secret key: the key polynomial evaluated at `index`.
+pub struct KeyShare {
+    pub index: u32,
+    secret: Scalar,
+}
+
+impl KeyShare {
+    /// The public check that a submitted share is genuine.
+    pub fn verification_key(&self) -> Point {
+        Point(self.secret * G)
+    }
+
+    pub fn partial_decrypt(&self, ciphertext: &Ciphertext) -> Point {
+        Point(self.secret * ciphertext.c1.0)
+    }
+}
+
+/// Shares are handed to trustees as `index-hex`.
+impl fmt::Display for KeyShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.index, hex::encode(self.secret.to_bytes()))
+    }
+}
+
+impl std::str::FromStr for KeyShare {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (index, secret) = s.trim().split_once('-').ok_or(())?;
+        let index: u32 = index.parse().map_err(|_| ())?;
+        let bytes: [u8; 32] = hex::decode(secret)
+            .map_err(|_| ())?
+            .try_into()
+            .map_err(|_| ())?;
+        let secret = Option::from(Scalar::from_canonical_bytes(bytes)).ok_or(())?;
+        match index {
+            0 => Err(()),
+            _ => Ok(KeyShare { index, secret }),
+        }
+    }
+}
+
+/// Generate a key pair and split its secret into `trustees` shares, any `threshold` of which
+/// can decrypt. The secret itself is dropped on return.
+pub fn deal(threshold: usize, trustees: usize) -> (Point, Vec<KeyShare>) {
+    let mut rng = rand::thread_rng();
+    let coefficients: Vec<Scalar> = (0..threshold).map(|_| Scalar::random(&mut rng)).collect();
+    let shares = (1..=trustees as u32)
+        .map(|index| {
+            let x = Scalar::from(index);
+            // Horner's rule, from the highest coefficient down.
+            let secret = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::ZERO, |acc, c| acc * x + c);
+            KeyShare { index, secret }
+        })
+        .collect();
+    (Point(coefficients[0] * G), shares)
+}
+
+/// Lagrange coefficient at zero for share `index` among `indices`.
+fn lagrange(index: u32, indices: &[u32]) -> Scalar {
+    let i = Scalar::from(index);
+    indices
+        .iter()
+        .filter(|j| **j != index)
+        .fold(Scalar::ONE, |acc, j| {
+            let j = Scalar::from(*j);
+            acc * j * (j - i).invert()
+        })
+}
+
+/// Decrypt `ciphertext` from at least `threshold` partial decryptions, as `(share index, partial)`.
+///
+/// Returns `None` if the partials do not combine to a count of at most `max`.
+pub fn combine(partials: &[(u32, Point)], ciphertext: &Ciphertext, max: u64) -> Option<u64> {
+    let indices: Vec<u32> = partials.iter().map(|(index, _)| *index).collect();
+    let shared: RistrettoPoint = partials
+        .iter()
+        .map(|(index, partial)| lagrange(*index, &indices) * partial.0)
+        .sum();
+    let target = ciphertext.c2.0 - shared;
+    let mut guess = RistrettoPoint::identity();
+    for m in 0..=max {
+        if guess == target {
+            return Some(m);
+        }
+        guess += G;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_threshold_of_trustees_decrypts_the_sum() {
+        let (public_key, shares) = deal(2, 3);
+        let votes = [1, 0, 1, 1, 0];
+        let sum: Ciphertext = votes.iter().map(|v| encrypt(&public_key, *v)).sum();
+
+        for pair in [[0, 1], [0, 2], [1, 2]] {
+            let partials: Vec<(u32, Point)> = pair
+                .iter()
+                .map(|i| (shares[*i].index, shares[*i].partial_decrypt(&sum)))
+                .collect();
+            assert_eq!(combine(&partials, &sum, votes.len() as u64), Some(3));
+        }
+    }
+
+    #[test]
+    fn too_few_trustees_cannot_decrypt() {
+        let (public_key, shares) = deal(2, 3);
+        let sum: Ciphertext = [1, 1].iter().map(|v| encrypt(&public_key, *v)).sum();
+        let partials = vec![(shares[0].index, shares[0].partial_decrypt(&sum))];
+        assert_eq!(combine(&partials, &sum, 2), None);
+    }
+
+    #[test]
+    fn shares_round_trip_through_text() {
+        let (_, shares) = deal(1, 1);
+        let text = shares[0].to_string();
+        let parsed: KeyShare = text.parse().unwrap();
+        assert_eq!(parsed.index, 1);
+        assert!(parsed.verification_key() == shares[0].verification_key());
+        assert!("0-00".parse::<KeyShare>().is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use crate::storage::StorageError;
+
+/// Why a command or component interaction could not be handled.
+///
+/// Handlers return these instead of replying with the problem themselves, and
+/// `respond_with_error` turns them into a private reply and a log line.
+#[derive(Debug)]
+pub enum Error {
+    /// The request cannot be carried out as asked, e.g. a missing option or an unknown poll.
+    User(String),
+    /// The user is not allowed to do this to the poll.
+    Permission(String),
+    Storage(StorageError),
+    Discord(serenity::Error),
+}
+
+impl Error {
+    /// Short name of the variant, for log lines.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::User(_) => "user",
+            Error::Permission(_) => "permission",
+            Error::Storage(_) => "storage",
+            Error::Discord(_) => "discord",
+        }
+    }
+
+    /// What to tell the user. Internal failures are not described in detail.
+    pub fn reply(&self) -> String {
+        match self {
+            Error::User(message) | Error::Permission(message) => message.clone(),
+            Error::Storage(_) => "The poll could not be saved, please try again.".to_string(),
+            Error::Discord(_) => "Something went wrong talking to Discord...".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::User(message) | Error::Permission(message) => write!(f, "{}", message),
+            Error::Storage(e) => write!(f, "{}", e),
+            Error::Discord(e) => write!(f, "discord error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<StorageError> for Error {
+    fn from(e: StorageError) -> Self {
+        Error::Storage(e)
+    }
+}
+
+impl From<serenity::Error> for Error {
+    fn from(e: serenity::Error) -> Self {
+        Error::Discord(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
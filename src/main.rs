@@ -1,34 +1,78 @@
-use std::{collections::HashMap, env, sync::Arc};
+mod ballot_box;
+mod chart;
+mod component_id;
+mod elgamal;
+mod error;
+mod storage;
+mod tally;
+mod verify;
 
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ballot_box::{BallotBox, Voter, VoterKey};
+use chart::Bar;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use component_id::{ComponentId, Control};
 use dashmap::{
     mapref::entry::Entry::{Occupied, Vacant},
     DashMap,
 };
 use dotenv::dotenv;
+use elgamal::{Ciphertext, KeyShare, Point};
+use error::{Error, Result};
+use serde::{Deserialize, Serialize};
 use serenity::{
     async_trait,
-    builder::{CreateActionRow, CreateButton},
+    builder::{
+        CreateActionRow, CreateApplicationCommandOption, CreateButton,
+        CreateInteractionResponseData, CreateSelectMenu,
+    },
     client::{Context, EventHandler},
     model::{
         gateway::Ready,
-        id::GuildId,
+        guild::Member,
+        id::{ChannelId, GuildId, MessageId, RoleId, UserId},
         interactions::{
             application_command::{
                 ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue,
                 ApplicationCommandOptionType,
             },
             message_component::{ButtonStyle, MessageComponentInteraction},
-            Interaction, InteractionResponseType,
+            Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
         },
+        permissions::Permissions,
         user::User,
     },
     prelude::*,
-    Client, Result,
+    Client,
 };
+use storage::{PollStore, SqliteStore};
+use tally::{instant_runoff, ranked_pairs, schulze, single_transferable_vote};
+use verify::{Board, BoardAction, Opening, Proof};
 
 const OPTION_SEPARATOR: &str = "|";
-const ID_SEPARATOR: &str = "<id:option>";
 const COUNT_LEADER: &str = "\nResponses: ";
+// Discord allows five action rows of five buttons, or a select menu of 25 options, per message.
+// One row is kept for the withdraw and abstain controls.
+const MAX_OPTIONS: usize = 25;
+const BUTTONS_PER_ROW: usize = 5;
+const MAX_OPTION_BUTTONS: usize = 20;
+const MAX_BUTTON_LABEL: usize = 80;
+const MAX_OPTION_LENGTH: usize = 100;
+const CLOSED_BANNER: &str = "\n**This poll is closed.**";
+// A year, longer than any poll should reasonably let votes be changed.
+const MAX_CHANGE_WINDOW: i64 = 60 * 24 * 365;
+// How often scheduled closing times are checked.
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(30);
+const DRAFT_EXPIRED: &str = "This ballot has expired, press the button on the poll to start again.";
 
 struct CommandCounter;
 
@@ -36,19 +80,445 @@ impl TypeMapKey for CommandCounter {
     type Value = Arc<DashMap<String, u64>>;
 }
 
-#[derive(Clone)]
-struct Poll {
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PollKind {
+    #[default]
+    Plurality,
+    Ranked,
+    Approval,
+    Score,
+}
+
+/// How ranked ballots are counted.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RankedMethod {
+    #[default]
+    InstantRunoff,
+    Schulze,
+    RankedPairs,
+    Stv,
+}
+
+/// When a voter may change or withdraw a vote they have already cast.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangePolicy {
+    #[default]
+    UntilClose,
+    Locked,
+    /// Changeable for this many minutes after the voter first voted.
+    Timed(i64),
+}
+
+impl ChangePolicy {
+    fn notice(&self) -> String {
+        match self {
+            ChangePolicy::UntilClose => "Votes can be changed until the poll closes.".to_string(),
+            ChangePolicy::Locked => "Votes are final once cast.".to_string(),
+            ChangePolicy::Timed(minutes) => format!(
+                "Votes can be changed for {} minutes after first voting. A withdrawn vote can be \
+                 cast again at any time.",
+                minutes
+            ),
+        }
+    }
+}
+
+/// Minimum turnout for a poll's result to count.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quorum {
+    /// At least this many responses, abstentions included.
+    Absolute(usize),
+    /// At least this percentage of the eligible electorate.
+    Share(f64),
+}
+
+impl Quorum {
+    /// Responses needed, or `None` if the electorate size is unknown. A quorum always needs at
+    /// least one response, even of an empty electorate.
+    fn required(&self, electorate: Option<usize>) -> Option<usize> {
+        match self {
+            Quorum::Absolute(n) => Some(*n),
+            Quorum::Share(percent) => {
+                electorate.map(|size| ((size as f64 * percent / 100.0).ceil() as usize).max(1))
+            }
+        }
+    }
+}
+
+/// Share of the votes cast, abstentions excluded, that the leading option needs to pass.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Threshold {
+    SimpleMajority,
+    TwoThirds,
+    Unanimity,
+}
+
+impl Threshold {
+    fn is_met(&self, votes: usize, cast: usize) -> bool {
+        if cast == 0 {
+            return false;
+        }
+        match self {
+            Threshold::SimpleMajority => votes * 2 > cast,
+            Threshold::TwoThirds => votes * 3 >= cast * 2,
+            Threshold::Unanimity => votes == cast,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Threshold::SimpleMajority => "more than half",
+            Threshold::TwoThirds => "at least two thirds",
+            Threshold::Unanimity => "all",
+        }
+    }
+}
+
+/// Key material of an encrypted poll. The secret key exists only as the trustees' shares.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Election {
+    public_key: Point,
+    threshold: usize,
+    /// Trustee holding share `i + 1`, with the public key their share must match.
+    trustees: Vec<(UserId, Point)>,
+    /// Each submitted share's partial decryptions of the summed ballots, one per option.
+    partials: BTreeMap<u32, Vec<Point>>,
+    /// Per-option counts, once enough trustees have submitted shares.
+    tally: Option<Vec<u64>>,
+}
+
+impl Election {
+    fn encrypt_choice(&self, options: &[String], choice: Option<&str>) -> Ballot {
+        Ballot::Encrypted {
+            ciphertexts: options
+                .iter()
+                .map(|o| elgamal::encrypt(&self.public_key, (Some(o.as_str()) == choice) as u64))
+                .collect(),
+        }
+    }
+}
+
+/// Inclusive range of scores a voter may give each option of a score poll.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct ScoreRange {
+    min: i64,
+    max: i64,
+}
+
+impl Default for ScoreRange {
+    fn default() -> Self {
+        ScoreRange { min: 0, max: 5 }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Ballot {
+    Choice(String),
+    Ranking(Vec<String>),
+    Approval {
+        approved: BTreeSet<String>,
+    },
+    Scores {
+        scores: BTreeMap<String, i64>,
+    },
+    /// A plurality vote in an encrypted poll: one ciphertext of 0 or 1 per option, all 0 for an
+    /// abstention.
+    Encrypted {
+        ciphertexts: Vec<Ciphertext>,
+    },
+    /// Counts towards turnout but not towards any option.
+    Abstain,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Poll {
+    /// Internal number standing in for the poll id in component ids. Never reused.
+    key: u64,
     owner: User,
-    responses: DashMap<User, String>,
+    /// The question alone; the poll's message is rendered from it and the settings below.
+    prompt: String,
+    created_at: Option<DateTime<Utc>>,
+    closed_at: Option<DateTime<Utc>>,
+    kind: PollKind,
+    options: Vec<String>,
+    score_range: Option<ScoreRange>,
+    method: RankedMethod,
+    seats: Option<usize>,
+    /// Ballots and "has voted" tokens, neither of which name the voter.
+    ballot_box: BallotBox,
     open: bool,
+    /// Where the poll was posted, so that it can be updated after the fact.
+    message: Option<(ChannelId, MessageId)>,
+    closes_at: Option<DateTime<Utc>>,
+    allow_abstain: bool,
+    change_policy: ChangePolicy,
+    /// Co-owners, who can do anything with the poll that its owner can.
+    admins: BTreeSet<UserId>,
+    admin_roles: BTreeSet<RoleId>,
+    /// The guild the poll was created in, used to size the electorate.
+    guild_id: Option<GuildId>,
+    /// If either is non-empty, only listed users and holders of listed roles may vote.
+    eligible_roles: BTreeSet<RoleId>,
+    eligible_users: BTreeSet<UserId>,
+    quorum: Option<Quorum>,
+    /// Only plurality and approval polls can have a pass threshold.
+    threshold: Option<Threshold>,
+    /// The public bulletin board of a verifiable plurality poll.
+    board: Option<Board>,
+    /// Set on encrypted plurality polls, whose ballots are only stored as ciphertexts.
+    election: Option<Election>,
+    /// A sealed poll's tally stays hidden, even from its administrators, until it is revealed.
+    sealed: bool,
+    revealed: bool,
+}
+
+impl Poll {
+    fn close(&mut self) {
+        if self.open {
+            self.open = false;
+            self.closed_at = Some(Utc::now());
+        }
+    }
+
+    /// Whether `user` may see results of, administer, close or delete this poll. Besides the
+    /// owner and co-owners, anyone who can manage the server is an administrator of every poll.
+    fn is_admin(&self, user: &User, member: Option<&Member>) -> bool {
+        if user.id == self.owner.id || self.admins.contains(&user.id) {
+            return true;
+        }
+        match member {
+            Some(member) => {
+                member.roles.iter().any(|r| self.admin_roles.contains(r))
+                    || member.permissions.is_some_and(|p| {
+                        p.intersects(Permissions::MANAGE_GUILD | Permissions::ADMINISTRATOR)
+                    })
+            }
+            None => false,
+        }
+    }
+
+    /// Until when `voter` may change their vote under a timed change policy.
+    fn change_deadline(&self, voter: &Voter) -> Option<DateTime<Utc>> {
+        let first_voted = self.ballot_box.first_voted(voter)?;
+        match self.change_policy {
+            ChangePolicy::Timed(minutes) => TimeDelta::try_minutes(minutes)
+                .and_then(|window| first_voted.checked_add_signed(window)),
+            _ => None,
+        }
+    }
+
+    /// Why `voter` may not change their vote right now, if the change policy forbids it. Only a
+    /// standing ballot is held to the policy: a voter who withdrew may always vote again.
+    fn change_refusal(&self, voter: &Voter) -> Option<String> {
+        self.ballot_box.get(voter)?;
+        match self.change_policy {
+            ChangePolicy::UntilClose => None,
+            ChangePolicy::Locked => Some("Your vote is final and cannot be changed.".to_string()),
+            ChangePolicy::Timed(minutes) => {
+                if self
+                    .change_deadline(voter)
+                    .is_some_and(|deadline| Utc::now() > deadline)
+                {
+                    Some(format!(
+                        "Votes can only be changed within {} minutes of first voting.",
+                        minutes
+                    ))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn is_restricted(&self) -> bool {
+        !self.eligible_roles.is_empty() || !self.eligible_users.is_empty()
+    }
+
+    fn is_eligible(&self, user: &User, member: Option<&Member>) -> bool {
+        !self.is_restricted()
+            || self.eligible_users.contains(&user.id)
+            || member.is_some_and(|m| m.roles.iter().any(|r| self.eligible_roles.contains(r)))
+    }
+
+    /// Why `user` may not vote right now, if anything stops them.
+    fn voting_refusal(
+        &self,
+        voter: &Voter,
+        user: &User,
+        member: Option<&Member>,
+    ) -> Option<String> {
+        if !self.is_eligible(user, member) {
+            return Some("You are not eligible to vote in this poll.".to_string());
+        }
+        self.change_refusal(voter)
+    }
+
+    /// Store a voter's ballot, or withdraw it when `ballot` is `None`, if eligibility and the
+    /// change policy allow. Eligibility is checked against `user`, who `voter` stands in for.
+    fn cast(
+        &mut self,
+        voter: &Voter,
+        user: &User,
+        member: Option<&Member>,
+        ballot: Option<Ballot>,
+    ) -> std::result::Result<(), String> {
+        if ballot.is_some() && !self.is_eligible(user, member) {
+            return Err("You are not eligible to vote in this poll.".to_string());
+        }
+        if let Some(refusal) = self.change_refusal(voter) {
+            return Err(refusal);
+        }
+        if let Some(board) = &mut self.board {
+            if let Some(old) = self.ballot_box.set_receipt(voter, None) {
+                board.append(BoardAction::Revoke(old.commitment));
+            }
+            if let Some(ballot) = &ballot {
+                let choice = match ballot {
+                    Ballot::Choice(choice) => Some(choice.clone()),
+                    _ => None,
+                };
+                let opening = Opening::new(&board.poll_id, choice);
+                board.append(BoardAction::Cast(opening.commitment.clone()));
+                self.ballot_box.set_receipt(voter, Some(opening));
+            }
+        }
+        let ballot = match (&self.election, ballot) {
+            (Some(election), Some(Ballot::Choice(choice))) => {
+                Some(election.encrypt_choice(&self.options, Some(&choice)))
+            }
+            (Some(election), Some(Ballot::Abstain)) => {
+                Some(election.encrypt_choice(&self.options, None))
+            }
+            (_, ballot) => ballot,
+        };
+        self.ballot_box.cast(voter, ballot);
+        Ok(())
+    }
+
+    /// Per option, the sum of every encrypted ballot's ciphertext for it.
+    fn encrypted_sums(&self) -> Vec<Ciphertext> {
+        (0..self.options.len())
+            .map(|i| {
+                self.ballot_box
+                    .ballots()
+                    .filter_map(|ballot| match ballot {
+                        Ballot::Encrypted { ciphertexts } => ciphertexts.get(i).copied(),
+                        _ => None,
+                    })
+                    .sum()
+            })
+            .collect()
+    }
 }
 
 struct PollData;
 
+struct BallotKey;
+
+impl TypeMapKey for BallotKey {
+    type Value = Arc<VoterKey>;
+}
+
+/// The anonymous stand-in for `user` in poll `poll_id`.
+async fn voter(ctx: &Context, poll_id: &str, user: UserId) -> Voter {
+    let data_read = ctx.data.read().await;
+    data_read
+        .get::<BallotKey>()
+        .expect("Expected BallotKey in TypeMap.")
+        .voter(poll_id, user)
+}
+
 impl TypeMapKey for PollData {
     type Value = Arc<DashMap<String, Poll>>;
 }
 
+/// Poll ids by poll key, for looking up the poll behind a button.
+struct PollKeys;
+
+impl TypeMapKey for PollKeys {
+    type Value = Arc<DashMap<u64, String>>;
+}
+
+async fn poll_id_for_key(ctx: &Context, key: u64) -> Option<String> {
+    let data_read = ctx.data.read().await;
+    data_read
+        .get::<PollKeys>()
+        .expect("Expected PollKeys in TypeMap.")
+        .get(&key)
+        .map(|kv| kv.value().clone())
+}
+
+/// A ballot in progress (a ranking or a list of scores), built up one select menu at a time
+/// before it is submitted.
+struct BallotDraft {
+    picks: Vec<String>,
+    poll_message: (ChannelId, MessageId),
+}
+
+struct BallotDrafts;
+
+impl TypeMapKey for BallotDrafts {
+    type Value = Arc<DashMap<(String, UserId), BallotDraft>>;
+}
+
+struct PollStorage;
+
+impl TypeMapKey for PollStorage {
+    type Value = Arc<dyn PollStore>;
+}
+
+/// Write `poll` to the store. Call this while still holding the poll's entry in `PollData`, so
+/// that concurrent changes to one poll are written in the order they were made.
+fn save_poll(data: &TypeMap, poll_id: &str, poll: &Poll) -> Result<()> {
+    let store = data
+        .get::<PollStorage>()
+        .expect("Expected PollStorage in TypeMap.");
+    Ok(store.save_poll(poll_id, poll)?)
+}
+
+/// Make `change` to a copy of `poll`, save the copy and only then put it in place of `poll`, so a
+/// poll whose change fails or cannot be saved stays as it was. Hold the poll's entry throughout,
+/// as for `save_poll`.
+fn update_poll<T>(
+    data: &TypeMap,
+    poll_id: &str,
+    poll: &mut Poll,
+    change: impl FnOnce(&mut Poll) -> Result<T>,
+) -> Result<T> {
+    let mut updated = poll.clone();
+    let result = change(&mut updated)?;
+    save_poll(data, poll_id, &updated)?;
+    *poll = updated;
+    Ok(result)
+}
+
+/// Delete a poll from the store and then from `PollData` and `PollKeys`, returning it if it
+/// existed.
+fn remove_poll(data: &TypeMap, poll_id: &str) -> Result<Option<Poll>> {
+    let poll_map = data
+        .get::<PollData>()
+        .expect("Expected PollData in TypeMap.")
+        .clone();
+    let entry = match poll_map.entry(poll_id.to_string()) {
+        Occupied(entry) => entry,
+        Vacant(_) => return Ok(None),
+    };
+    data.get::<PollStorage>()
+        .expect("Expected PollStorage in TypeMap.")
+        .delete_poll(poll_id)?;
+    let (_, poll) = entry.remove_entry();
+    data.get::<PollKeys>()
+        .expect("Expected PollKeys in TypeMap.")
+        .remove(&poll.key);
+    Ok(Some(poll))
+}
+
 async fn increment_command(ctx: &Context, command: &str) {
     let data_read = ctx.data.read().await;
     let counter = data_read
@@ -59,6 +529,30 @@ async fn increment_command(ctx: &Context, command: &str) {
     *entry += 1;
 }
 
+/// The value of a required string option of a command.
+fn string_option<'a>(
+    options: &'a HashMap<String, ApplicationCommandInteractionDataOptionValue>,
+    name: &str,
+) -> Result<&'a String> {
+    match options.get(name) {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => Ok(s),
+        Some(_) => Err(Error::User(format!("The {} option must be text.", name))),
+        None => Err(Error::User(format!("The {} option is required.", name))),
+    }
+}
+
+fn no_such_poll() -> Error {
+    Error::User("No poll with that ID.".to_string())
+}
+
+fn still_sealed() -> Error {
+    Error::User("The results of this poll are still sealed or encrypted.".to_string())
+}
+
+fn not_admin() -> Error {
+    Error::Permission("Not an administrator of this poll.".to_string())
+}
+
 async fn reply_to_command(
     ctx: &Context,
     command: &ApplicationCommandInteraction,
@@ -70,392 +564,3120 @@ async fn reply_to_command(
                 .kind(InteractionResponseType::ChannelMessageWithSource)
                 .interaction_response_data(|message| message.content(content))
         })
-        .await
+        .await?;
+    Ok(())
+}
+
+async fn reply_to_command_ephemeral(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    content: &String,
+) -> Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content);
+                    message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    message
+                })
+        })
+        .await?;
+    Ok(())
 }
 
-fn create_poll_button(id: &String, option: &String) -> CreateButton {
+fn create_poll_button(key: u64, index: usize, option: &String, disabled: bool) -> CreateButton {
     let mut butt = CreateButton::default();
-    butt.custom_id(format!("{}{}{}", id, ID_SEPARATOR, option));
+    butt.custom_id(ComponentId::new(key, Control::Choose(index)));
     butt.label(option);
     butt.style(ButtonStyle::Primary);
+    butt.disabled(disabled);
     butt
 }
 
-fn create_poll_row(id: &String, options: &Vec<String>) -> CreateActionRow {
-    let mut row = CreateActionRow::default();
-    for option in options.iter() {
-        row.add_button(create_poll_button(id, option));
+/// One button per option, five to a row, or a single-choice select menu if there are too many
+/// options or a label is too long for a button.
+fn create_poll_rows(key: u64, options: &[String], disabled: bool) -> Vec<CreateActionRow> {
+    if options.len() > MAX_OPTION_BUTTONS
+        || options.iter().any(|o| o.chars().count() > MAX_BUTTON_LABEL)
+    {
+        let mut menu = CreateSelectMenu::default();
+        menu.custom_id(ComponentId::new(key, Control::ChoosePick));
+        menu.placeholder("Select an option");
+        menu.disabled(disabled);
+        menu.options(|menu_options| {
+            for (i, option) in options.iter().enumerate() {
+                menu_options.create_option(|o| o.label(option).value(i));
+            }
+            menu_options
+        });
+        let mut row = CreateActionRow::default();
+        row.add_select_menu(menu);
+        return vec![row];
     }
-    row
-}
-
-async fn handle_poll_new(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
-    let owner: &User = &command.user;
 
-    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
-        .data
-        .options
-        .iter()
-        .filter_map(|o| match &o.resolved {
-            Some(v) => Some((o.name.clone(), v.clone())),
-            _ => None,
+    options
+        .chunks(BUTTONS_PER_ROW)
+        .enumerate()
+        .map(|(chunk_index, chunk)| {
+            let mut row = CreateActionRow::default();
+            for (i, option) in chunk.iter().enumerate() {
+                let index = chunk_index * BUTTONS_PER_ROW + i;
+                row.add_button(create_poll_button(key, index, option, disabled));
+            }
+            row
         })
-        .collect();
+        .collect()
+}
 
-    let poll_id = match options.get("id").expect("expected poll id") {
-        ApplicationCommandInteractionDataOptionValue::String(s) => s,
-        _ => panic!("poll id must be String"),
-    };
+/// A single button that opens a private, step-by-step ballot for ranked and score polls.
+fn create_draft_poll_row(key: u64, label: &str, disabled: bool) -> CreateActionRow {
+    let mut butt = CreateButton::default();
+    butt.custom_id(ComponentId::new(key, Control::DraftStart));
+    butt.label(label);
+    butt.style(ButtonStyle::Primary);
+    butt.disabled(disabled);
+    let mut row = CreateActionRow::default();
+    row.add_button(butt);
+    row
+}
 
-    let poll_prompt = match options.get("prompt").expect("expected poll prompt") {
-        ApplicationCommandInteractionDataOptionValue::String(s) => s,
-        _ => panic!("poll prompt must be String"),
-    };
+fn create_approval_poll_row(key: u64, options: &[String], disabled: bool) -> CreateActionRow {
+    let mut menu = CreateSelectMenu::default();
+    menu.custom_id(ComponentId::new(key, Control::ApprovePick));
+    menu.placeholder("Select every option you approve of");
+    menu.disabled(disabled);
+    menu.min_values(0);
+    menu.max_values(options.len() as u64);
+    menu.options(|menu_options| {
+        for (i, option) in options.iter().enumerate() {
+            menu_options.create_option(|o| o.label(option).value(i));
+        }
+        menu_options
+    });
+    let mut row = CreateActionRow::default();
+    row.add_select_menu(menu);
+    row
+}
 
-    let poll_options = {
-        let string = match options.get("options").expect("expected poll options") {
-            ApplicationCommandInteractionDataOptionValue::String(s) => s,
-            _ => panic!("poll options must be String"),
-        };
-        string
-            .split(OPTION_SEPARATOR)
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>()
+/// The voting controls for a poll, disabled once it has closed.
+fn create_poll_components(poll: &Poll) -> Vec<CreateActionRow> {
+    let disabled = !poll.open;
+    let key = poll.key;
+    let mut rows = match poll.kind {
+        PollKind::Plurality => create_poll_rows(key, &poll.options, disabled),
+        PollKind::Ranked => vec![create_draft_poll_row(key, "Rank options", disabled)],
+        PollKind::Score => vec![create_draft_poll_row(key, "Score options", disabled)],
+        PollKind::Approval => vec![create_approval_poll_row(key, &poll.options, disabled)],
     };
 
-    if {
-        let data_read = ctx.data.read().await;
-        let poll_map = data_read
-            .get::<PollData>()
-            .expect("Expected PollData in TypeMap.")
-            .clone();
-        let success = match poll_map.entry(poll_id.clone()) {
-            Occupied(_) => false,
-            Vacant(entry) => {
-                entry.insert(Poll {
-                    owner: owner.clone(),
-                    responses: DashMap::default(),
-                    open: true,
-                });
-                true
-            }
-        };
-        success
-    } {
-        command
-            .create_interaction_response(&ctx.http, |response| {
-                response
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|message| {
-                        message.content(format!("{}{}{}", poll_prompt, COUNT_LEADER, 0));
-                        message.components(|components| {
-                            components.add_action_row(create_poll_row(&poll_id, &poll_options))
-                        });
-                        message
-                    })
-            })
-            .await
-    } else {
-        command
-            .create_interaction_response(&ctx.http, |response| {
-                response
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|message| {
-                        message.content("Poll with that id already exists.");
-                        message
-                    })
-            })
-            .await
+    let mut row = CreateActionRow::default();
+    if poll.allow_abstain {
+        let mut abstain = CreateButton::default();
+        abstain.custom_id(ComponentId::new(key, Control::Abstain));
+        abstain.label("Abstain");
+        abstain.style(ButtonStyle::Secondary);
+        abstain.disabled(disabled);
+        row.add_button(abstain);
     }
+    let mut withdraw = CreateButton::default();
+    withdraw.custom_id(ComponentId::new(key, Control::Withdraw));
+    withdraw.label("Withdraw my vote");
+    withdraw.style(ButtonStyle::Danger);
+    withdraw.disabled(disabled);
+    row.add_button(withdraw);
+    rows.push(row);
+
+    rows
 }
 
-async fn handle_poll_results(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
-    let user: &User = &command.user;
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
 
-    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
-        .data
-        .options
+fn format_ranking(ranking: &[String]) -> String {
+    ranking
         .iter()
-        .filter_map(|o| match &o.resolved {
-            Some(v) => Some((o.name.clone(), v.clone())),
-            _ => None,
-        })
-        .collect();
+        .enumerate()
+        .map(|(i, option)| format!("\n{}. {}", i + 1, option))
+        .collect()
+}
+
+fn create_draft_controls_row(key: u64, label: &str, can_submit: bool) -> CreateActionRow {
+    let mut submit = CreateButton::default();
+    submit.custom_id(ComponentId::new(key, Control::DraftSubmit));
+    submit.label(label);
+    submit.style(ButtonStyle::Success);
+    submit.disabled(!can_submit);
+
+    let mut reset = CreateButton::default();
+    reset.custom_id(ComponentId::new(key, Control::DraftReset));
+    reset.label("Start over");
+    reset.style(ButtonStyle::Secondary);
+
+    let mut row = CreateActionRow::default();
+    row.add_button(submit);
+    row.add_button(reset);
+    row
+}
 
-    let poll_id = match options.get("id").expect("expected poll id") {
-        ApplicationCommandInteractionDataOptionValue::String(s) => s,
-        _ => panic!("poll id must be String"),
+fn format_scores<'a>(scores: impl Iterator<Item = (&'a String, &'a String)>) -> String {
+    scores
+        .map(|(option, score)| format!("\n{}: {}", option, score))
+        .collect()
+}
+
+fn render_score_draft(
+    message: &mut CreateInteractionResponseData,
+    key: u64,
+    options: &[String],
+    range: ScoreRange,
+    scores: &[String],
+) {
+    let mut content = if scores.is_empty() {
+        "You have not scored any options yet.".to_string()
+    } else {
+        format!(
+            "Your scores so far:{}",
+            format_scores(options.iter().zip(scores.iter()))
+        )
     };
+    let next = options.get(scores.len());
+    if let Some(option) = next {
+        content.push_str(&format!(
+            "\nScore {} ({} of {}), from {} to {}.",
+            option,
+            scores.len() + 1,
+            options.len(),
+            range.min,
+            range.max
+        ));
+    }
+    message.content(content);
 
-    let content = {
-        if let Some(poll) = {
-            let data_read = ctx.data.read().await;
-            let poll_map = data_read
-                .get::<PollData>()
-                .expect("Expected PollData in TypeMap.")
-                .clone();
-            poll_map.get(poll_id).map(|kv| kv.value().clone())
-        } {
-            let counts = {
-                let mut counts: HashMap<String, u64> = HashMap::new();
-                for kv in poll.responses.iter() {
-                    *counts.entry(kv.value().clone()).or_insert(0) += 1;
+    message.components(|components| {
+        if let Some(option) = next {
+            let mut menu = CreateSelectMenu::default();
+            menu.custom_id(ComponentId::new(key, Control::DraftPick));
+            menu.placeholder(format!("Score for {}", option));
+            menu.options(|menu_options| {
+                for score in range.min..=range.max {
+                    menu_options.create_option(|o| o.label(score).value(score));
                 }
-                counts
-            };
+                menu_options
+            });
+            let mut row = CreateActionRow::default();
+            row.add_select_menu(menu);
+            components.add_action_row(row);
+        }
 
-            let report = {
-                let mut report = format!("Results for poll id {}", poll_id);
-                for (k, v) in counts.iter() {
-                    report.push_str(&format!("\n{}\t{}", v, k));
-                }
-                report
-            };
+        components.add_action_row(create_draft_controls_row(
+            key,
+            "Submit scores",
+            next.is_none(),
+        ))
+    });
+}
 
-            if user == &poll.owner {
-                match poll.owner.create_dm_channel(&ctx.http).await {
-                    Ok(channel) => {
-                        match channel
-                            .send_message(&ctx.http, |message| {
-                                message.content(report);
-                                message
-                            })
-                            .await
-                        {
-                            Ok(_message) => "Results sent by direct message.",
-                            Err(e) => {
-                                println!("Failed to send message: {}", e);
-                                "Failed to send results..."
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("Failed to send message: {}", e);
-                        "Failed to send results..."
+fn render_draft(message: &mut CreateInteractionResponseData, poll: &Poll, picks: &[String]) {
+    match poll.kind {
+        PollKind::Score => render_score_draft(
+            message,
+            poll.key,
+            &poll.options,
+            poll.score_range.unwrap_or_default(),
+            picks,
+        ),
+        _ => render_ranking_draft(message, poll.key, &poll.options, picks),
+    }
+}
+
+fn render_ranking_draft(
+    message: &mut CreateInteractionResponseData,
+    key: u64,
+    options: &[String],
+    ranking: &[String],
+) {
+    let remaining: Vec<&String> = options.iter().filter(|o| !ranking.contains(o)).collect();
+
+    let mut content = if ranking.is_empty() {
+        "Your ranking is empty.".to_string()
+    } else {
+        format!("Your ranking so far:{}", format_ranking(ranking))
+    };
+    if !remaining.is_empty() {
+        content.push_str(&format!(
+            "\nPick your {} choice, or submit to leave the rest unranked.",
+            ordinal(ranking.len() + 1)
+        ));
+    }
+    message.content(content);
+
+    message.components(|components| {
+        if !remaining.is_empty() {
+            let mut menu = CreateSelectMenu::default();
+            menu.custom_id(ComponentId::new(key, Control::DraftPick));
+            menu.placeholder(format!("{} choice", ordinal(ranking.len() + 1)));
+            menu.options(|menu_options| {
+                for (i, option) in options.iter().enumerate() {
+                    if !ranking.contains(option) {
+                        menu_options.create_option(|o| o.label(option).value(i));
                     }
                 }
-            } else {
-                "Not an owner of this poll."
-            }
+                menu_options
+            });
+            let mut row = CreateActionRow::default();
+            row.add_select_menu(menu);
+            components.add_action_row(row);
+        }
+
+        components.add_action_row(create_draft_controls_row(
+            key,
+            "Submit ranking",
+            !ranking.is_empty(),
+        ))
+    });
+}
+
+/// Describe a voter's stored ballot back to them.
+fn describe_ballot(poll: &Poll, ballot: &Ballot) -> String {
+    match ballot {
+        Ballot::Choice(choice) => format!("You voted for {}.", choice),
+        Ballot::Abstain => "You abstained.".to_string(),
+        Ballot::Encrypted { .. } => "Your vote is stored encrypted.".to_string(),
+        Ballot::Ranking(ranking) => format!("Your ranking:{}", format_ranking(ranking)),
+        Ballot::Approval { approved } => {
+            let approved: Vec<&str> = poll
+                .options
+                .iter()
+                .filter(|o| approved.contains(*o))
+                .map(|o| o.as_str())
+                .collect();
+            format!("You approved {}.", approved.join(", "))
+        }
+        Ballot::Scores { scores } => {
+            let scores: Vec<(&String, String)> = poll
+                .options
+                .iter()
+                .filter_map(|o| scores.get(o).map(|score| (o, score.to_string())))
+                .collect();
+            format!(
+                "Your scores:{}",
+                format_scores(scores.iter().map(|(o, score)| (*o, score)))
+            )
+        }
+    }
+}
+
+/// The text of a poll's public message, rendered from the poll itself.
+fn render_poll_message(poll: &Poll) -> String {
+    let mut content = poll.prompt.clone();
+    if let (true, Some(closes_at)) = (poll.open, poll.closes_at) {
+        content.push_str(&format!("\nCloses <t:{}:R>", closes_at.timestamp()));
+    }
+    content.push_str(&format!("\n{}", poll.change_policy.notice()));
+    if poll.is_restricted() {
+        let mut eligible: Vec<String> = poll
+            .eligible_roles
+            .iter()
+            .map(|r| format!("<@&{}>", r))
+            .collect();
+        eligible.extend(poll.eligible_users.iter().map(|u| format!("<@{}>", u)));
+        content.push_str(&format!("\nOnly open to {}.", eligible.join(", ")));
+    }
+    match poll.quorum {
+        Some(Quorum::Absolute(n)) => content.push_str(&format!("\nQuorum: {} responses.", n)),
+        Some(Quorum::Share(percent)) => {
+            content.push_str(&format!("\nQuorum: {}% of eligible voters.", percent))
+        }
+        None => {}
+    }
+    if poll.board.is_some() {
+        content.push_str(
+            "\nVerifiable: every vote is committed to a public board (see /poll-board) and \
+             voters are sent a receipt.",
+        );
+    }
+    if let Some(election) = &poll.election {
+        content.push_str(&format!(
+            "\nEncrypted: results stay sealed until {} of {} trustees decrypt them.",
+            election.threshold,
+            election.trustees.len()
+        ));
+    }
+    if poll.sealed {
+        content.push_str(
+            "\nSealed: only the number of responses shows until the results are revealed.",
+        );
+    }
+    if let Some(threshold) = poll.threshold {
+        content.push_str(&format!(
+            "\nAn option passes with {} of the votes cast.",
+            threshold.describe()
+        ));
+    }
+    content.push_str(&format!("{}{}", COUNT_LEADER, poll.ballot_box.len()));
+    if !poll.open {
+        content.push_str(CLOSED_BANNER);
+    }
+    content
+}
+
+fn poll_report(poll_id: &String, poll: &Poll, electorate: Option<usize>) -> String {
+    if poll.sealed && !poll.revealed {
+        return format!(
+            "Results for poll id {} are sealed until they are revealed with /poll-reveal. \
+             Responses: {}",
+            poll_id,
+            poll.ballot_box.len()
+        );
+    }
+
+    let mut report = match poll.kind {
+        _ if poll.election.is_some() => encrypted_report(poll_id, poll),
+        PollKind::Plurality => plurality_report(poll_id, poll),
+        PollKind::Ranked => ranked_report(poll_id, poll),
+        PollKind::Approval => approval_report(poll_id, poll),
+        PollKind::Score => score_report(poll_id, poll),
+    };
+
+    let abstentions = poll
+        .ballot_box
+        .ballots()
+        .filter(|response| matches!(response, Ballot::Abstain))
+        .count();
+    if poll.election.is_none() && (poll.allow_abstain || abstentions > 0) {
+        report.push_str(&format!(
+            "\nAbstentions: {} of {} responses",
+            abstentions,
+            poll.ballot_box.len()
+        ));
+    }
+    report.push_str(&governance_report(poll, electorate));
+    if let Some(electorate) = electorate {
+        let share = if electorate > 0 {
+            100.0 * poll.ballot_box.len() as f64 / electorate as f64
+        } else {
+            0.0
+        };
+        report.push_str(&format!(
+            "\nTurnout: {} of {} eligible voters ({:.1}%)",
+            poll.ballot_box.len(),
+            electorate,
+            share
+        ));
+    }
+    report
+}
+
+/// The option with the most votes, its vote count and the number of non-abstaining voters, or
+/// `None` while an encrypted poll's tally is still sealed.
+///
+/// A tie for the lead has no single leading option.
+fn leading_option(poll: &Poll) -> Option<(Option<(String, usize)>, usize)> {
+    let mut counts: Vec<(&String, usize)> = poll.options.iter().map(|o| (o, 0)).collect();
+    let mut cast = 0;
+    if let Some(election) = &poll.election {
+        for ((_, count), n) in counts.iter_mut().zip(election.tally.as_ref()?) {
+            *count = *n as usize;
+            cast += *n as usize;
+        }
+    }
+    for response in poll.ballot_box.ballots() {
+        match response {
+            Ballot::Choice(choice) => {
+                cast += 1;
+                if let Some((_, count)) = counts.iter_mut().find(|(o, _)| *o == choice) {
+                    *count += 1;
+                }
+            }
+            Ballot::Approval { approved } => {
+                cast += 1;
+                for (option, count) in counts.iter_mut() {
+                    if approved.contains(*option) {
+                        *count += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let leader = match counts.as_slice() {
+        [(option, votes), rest @ ..] if rest.first().is_none_or(|(_, next)| next < votes) => {
+            Some((option.to_string(), *votes))
+        }
+        _ => None,
+    };
+    Some((leader, cast))
+}
+
+/// Whether quorum was reached and the motion passed, for polls that set either.
+fn governance_report(poll: &Poll, electorate: Option<usize>) -> String {
+    let mut report = String::new();
+    let turnout = poll.ballot_box.len();
+    let quorum_met = match poll.quorum.map(|q| q.required(electorate)) {
+        None => true,
+        Some(None) => {
+            report.push_str("\nQuorum unknown: could not count the eligible voters");
+            false
+        }
+        Some(Some(required)) => {
+            let met = turnout >= required;
+            report.push_str(&format!(
+                "\nQuorum {}: {} of {} required responses",
+                if met { "met" } else { "not met" },
+                turnout,
+                required
+            ));
+            met
+        }
+    };
+
+    if let Some(threshold) = poll.threshold {
+        let outcome = match leading_option(poll) {
+            _ if !quorum_met => "No option passed: quorum not met".to_string(),
+            None => "Outcome undecided: the results are still encrypted".to_string(),
+            Some((_, 0)) => "No option passed: no votes were cast".to_string(),
+            Some((None, cast)) => {
+                format!("No option passed: no option leads among {} votes", cast)
+            }
+            // The threshold is checked against whichever option leads, so name it: on a Yes|No
+            // motion a passing No rejects the motion.
+            Some((Some((option, votes)), cast)) => format!(
+                "{}: {} has {} of {} votes ({:.1}%), needing {}",
+                if threshold.is_met(votes, cast) {
+                    format!("{} passed", option)
+                } else {
+                    "No option passed".to_string()
+                },
+                option,
+                votes,
+                cast,
+                100.0 * votes as f64 / cast as f64,
+                threshold.describe()
+            ),
+        };
+        report.push_str(&format!("\n{}", outcome));
+    }
+    report
+}
+
+fn encrypted_report(poll_id: &String, poll: &Poll) -> String {
+    let election = match &poll.election {
+        Some(election) => election,
+        None => return plurality_report(poll_id, poll),
+    };
+    match &election.tally {
+        Some(tally) => {
+            let mut report = format!(
+                "Results for poll id {} (encrypted, decrypted by trustees)",
+                poll_id
+            );
+            for (option, count) in poll.options.iter().zip(tally) {
+                report.push_str(&format!("\n{}\t{}", count, option));
+            }
+            let cast: u64 = tally.iter().sum();
+            report.push_str(&format!(
+                "\nAbstentions: {} of {} responses",
+                poll.ballot_box.len() as u64 - cast,
+                poll.ballot_box.len()
+            ));
+            report
+        }
+        None => format!(
+            "Results for poll id {} are encrypted until {} of {} trustees submit their key \
+             shares after the poll closes ({} so far). Responses: {}",
+            poll_id,
+            election.threshold,
+            election.trustees.len(),
+            election.partials.len(),
+            poll.ballot_box.len()
+        ),
+    }
+}
+
+fn plurality_report(poll_id: &String, poll: &Poll) -> String {
+    let mut counts: Vec<(&String, u64)> = poll.options.iter().map(|o| (o, 0)).collect();
+    for response in poll.ballot_box.ballots() {
+        if let Ballot::Choice(choice) = response {
+            if let Some((_, count)) = counts.iter_mut().find(|(o, _)| *o == choice) {
+                *count += 1;
+            }
+        }
+    }
+
+    let mut report = format!("Results for poll id {}", poll_id);
+    for (k, v) in counts.iter() {
+        report.push_str(&format!("\n{}\t{}", v, k));
+    }
+    report
+}
+
+fn approval_report(poll_id: &String, poll: &Poll) -> String {
+    let mut counts: Vec<(&String, u64)> = poll.options.iter().map(|o| (o, 0)).collect();
+    for response in poll.ballot_box.ballots() {
+        if let Ballot::Approval { approved } = response {
+            for (option, count) in counts.iter_mut() {
+                if approved.contains(*option) {
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    let mut report = format!(
+        "Results for poll id {} (approval, {} voters)",
+        poll_id,
+        poll.ballot_box.len()
+    );
+    for (k, v) in counts.iter() {
+        report.push_str(&format!("\n{}\t{}", v, k));
+    }
+    report
+}
+
+fn median(sorted: &[i64]) -> Option<f64> {
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[n / 2] as f64),
+        n => Some((sorted[n / 2 - 1] + sorted[n / 2]) as f64 / 2.0),
+    }
+}
+
+fn score_report(poll_id: &String, poll: &Poll) -> String {
+    let range = poll.score_range.unwrap_or_default();
+
+    let mut report = format!(
+        "Results for poll id {} (score {} to {}, {} voters)",
+        poll_id,
+        range.min,
+        range.max,
+        poll.ballot_box.len()
+    );
+    for option in poll.options.iter() {
+        let mut scores: Vec<i64> = poll
+            .ballot_box
+            .ballots()
+            .filter_map(|response| match response {
+                Ballot::Scores { scores } => scores.get(option).copied(),
+                _ => None,
+            })
+            .collect();
+        scores.sort_unstable();
+
+        report.push_str(&format!("\n{}", option));
+        if let Some(median) = median(&scores) {
+            let mean = scores.iter().sum::<i64>() as f64 / scores.len() as f64;
+            report.push_str(&format!("\tmean {:.2}\tmedian {:.1}", mean, median));
+        }
+        let distribution: Vec<String> = (range.min..=range.max)
+            .map(|score| {
+                let count = scores.iter().filter(|&&s| s == score).count();
+                format!("{}: {}", score, count)
+            })
+            .collect();
+        report.push_str(&format!("\n\t{}", distribution.join(", ")));
+    }
+    report
+}
+
+/// Lay out a square table of pairwise counts, numbering the options to keep columns narrow.
+fn format_matrix(options: &[String], matrix: &[Vec<usize>]) -> String {
+    let mut table = String::new();
+    for (i, option) in options.iter().enumerate() {
+        table.push_str(&format!("\n{}. {}", i + 1, option));
+    }
+    table.push('\n');
+    for i in 0..options.len() {
+        table.push_str(&format!("\t{}", i + 1));
+    }
+    for (i, row) in matrix.iter().enumerate() {
+        table.push_str(&format!("\n{}", i + 1));
+        for (j, count) in row.iter().enumerate() {
+            if i == j {
+                table.push_str("\t-");
+            } else {
+                table.push_str(&format!("\t{}", count));
+            }
+        }
+    }
+    table
+}
+
+fn format_option_list(options: &[String], indices: &[usize]) -> String {
+    indices
+        .iter()
+        .map(|&i| options[i].as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn ranked_report(poll_id: &String, poll: &Poll) -> String {
+    let ballots: Vec<&[String]> = poll
+        .ballot_box
+        .ballots()
+        .filter_map(|response| match response {
+            Ballot::Ranking(ranking) => Some(ranking.as_slice()),
+            _ => None,
+        })
+        .collect();
+
+    match poll.method {
+        RankedMethod::InstantRunoff => {
+            let (rounds, winner) = instant_runoff(&poll.options, &ballots);
+
+            let mut report = format!(
+                "Results for poll id {} (instant runoff, {} ballots)",
+                poll_id,
+                ballots.len()
+            );
+            for (i, round) in rounds.iter().enumerate() {
+                report.push_str(&format!("\nRound {}", i + 1));
+                for (option, count) in round.counts.iter() {
+                    report.push_str(&format!("\n{}\t{}", count, option));
+                }
+                if round.exhausted > 0 {
+                    report.push_str(&format!("\n{}\t(exhausted)", round.exhausted));
+                }
+                if let Some(eliminated) = &round.eliminated {
+                    report.push_str(&format!("\nEliminated: {}", eliminated));
+                }
+            }
+            match winner {
+                Some(winner) => report.push_str(&format!("\nWinner: {}", winner)),
+                None => report.push_str("\nNo winner."),
+            }
+            report
+        }
+        RankedMethod::Schulze => {
+            let result = schulze(&poll.options, &ballots);
+
+            let mut report = format!(
+                "Results for poll id {} (Schulze, {} ballots)",
+                poll_id,
+                ballots.len()
+            );
+            report.push_str("\nPairwise preferences (row over column)");
+            report.push_str(&format_matrix(&poll.options, &result.preferences));
+            report.push_str("\nStrongest paths (row to column)");
+            report.push_str(&format_matrix(&poll.options, &result.strongest_paths));
+            report.push_str(&format!(
+                "\nSmith set: {}",
+                format_option_list(&poll.options, &result.smith_set)
+            ));
+            match result.winners.as_slice() {
+                [] => report.push_str("\nNo winner."),
+                [winner] => report.push_str(&format!("\nWinner: {}", poll.options[*winner])),
+                winners => report.push_str(&format!(
+                    "\nTied winners: {}",
+                    format_option_list(&poll.options, winners)
+                )),
+            }
+            report
+        }
+        RankedMethod::RankedPairs => {
+            let result = ranked_pairs(&poll.options, &ballots);
+
+            let mut report = format!(
+                "Results for poll id {} (ranked pairs, {} ballots)",
+                poll_id,
+                ballots.len()
+            );
+            report.push_str("\nPairwise preferences (row over column)");
+            report.push_str(&format_matrix(&poll.options, &result.preferences));
+            for &(i, j, locked) in result.pairs.iter() {
+                report.push_str(&format!(
+                    "\n{} {} over {} ({} to {})",
+                    if locked { "Locked" } else { "Skipped" },
+                    poll.options[i],
+                    poll.options[j],
+                    result.preferences[i][j],
+                    result.preferences[j][i]
+                ));
+            }
+            report.push_str(&format!(
+                "\nSmith set: {}",
+                format_option_list(&poll.options, &result.smith_set)
+            ));
+            match result.winner {
+                Some(winner) => report.push_str(&format!("\nWinner: {}", poll.options[winner])),
+                None => report.push_str("\nNo winner."),
+            }
+            report
+        }
+        RankedMethod::Stv => {
+            let seats = poll.seats.unwrap_or(1);
+            let result = single_transferable_vote(&poll.options, &ballots, seats);
+
+            let mut report = format!(
+                "Results for poll id {} (single transferable vote, {} seats, {} ballots)\nQuota: {}",
+                poll_id,
+                seats,
+                ballots.len(),
+                result.quota
+            );
+            for (i, round) in result.rounds.iter().enumerate() {
+                report.push_str(&format!("\nRound {}", i + 1));
+                for (option, votes) in round.votes.iter() {
+                    report.push_str(&format!("\n{:.2}\t{}", votes, option));
+                }
+                if round.exhausted > 0.0 {
+                    report.push_str(&format!("\n{:.2}\t(exhausted)", round.exhausted));
+                }
+                for (option, transfer) in round.elected.iter() {
+                    if *transfer > 0.0 {
+                        report.push_str(&format!(
+                            "\nElected: {}, surplus transferred at {:.4}",
+                            option, transfer
+                        ));
+                    } else {
+                        report.push_str(&format!("\nElected: {}", option));
+                    }
+                }
+                if let Some(excluded) = &round.excluded {
+                    report.push_str(&format!("\nExcluded: {}", excluded));
+                }
+            }
+            report.push_str(&format!("\nElected: {}", result.elected.join(", ")));
+            report
+        }
+    }
+}
+
+async fn handle_poll_new(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let owner: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let poll_prompt = string_option(&options, "prompt")?;
+
+    let poll_options = {
+        let string = string_option(&options, "options")?;
+        string
+            .split(OPTION_SEPARATOR)
+            .map(|s| s.trim().to_string())
+            .collect::<Vec<String>>()
+    };
+
+    let option_problem = if poll_options.len() > MAX_OPTIONS {
+        Some(format!("A poll can have at most {} options.", MAX_OPTIONS))
+    } else if poll_options.iter().any(|o| o.is_empty()) {
+        Some("Options cannot be empty.".to_string())
+    } else if poll_options
+        .iter()
+        .any(|o| o.chars().count() > MAX_OPTION_LENGTH)
+    {
+        Some(format!(
+            "Options can be at most {} characters long.",
+            MAX_OPTION_LENGTH
+        ))
+    } else if poll_options
+        .iter()
+        .enumerate()
+        .any(|(i, o)| poll_options[..i].contains(o))
+    {
+        Some("Options must all be different.".to_string())
+    } else {
+        None
+    };
+
+    if let Some(problem) = option_problem {
+        return Err(Error::User(problem));
+    }
+
+    let poll_kind = match options.get("kind") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "ranked" => {
+            PollKind::Ranked
+        }
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "approval" => {
+            PollKind::Approval
+        }
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "score" => {
+            PollKind::Score
+        }
+        _ => PollKind::Plurality,
+    };
+
+    let ranked_method = match options.get("method") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "schulze" => {
+            Some(RankedMethod::Schulze)
+        }
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "ranked-pairs" => {
+            Some(RankedMethod::RankedPairs)
+        }
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "stv" => {
+            Some(RankedMethod::Stv)
+        }
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "instant-runoff" => {
+            Some(RankedMethod::InstantRunoff)
+        }
+        _ => None,
+    };
+
+    let seats = match options.get("seats") {
+        Some(ApplicationCommandInteractionDataOptionValue::Integer(i)) => {
+            if poll_kind != PollKind::Ranked {
+                return Err(Error::User(
+                    "Seats can only be set on a ranked poll.".to_string(),
+                ));
+            }
+            *i
+        }
+        _ => 1,
+    };
+
+    if poll_kind == PollKind::Ranked && (seats < 1 || seats as usize >= poll_options.len()) {
+        return Err(Error::User(
+            "Seats must be at least 1 and fewer than the number of options.".to_string(),
+        ));
+    }
+
+    // Every method but STV elects a single winner, so more seats implies STV unless another
+    // method was asked for.
+    let ranked_method = match ranked_method {
+        None if seats > 1 => RankedMethod::Stv,
+        None => RankedMethod::InstantRunoff,
+        Some(RankedMethod::Stv) => RankedMethod::Stv,
+        Some(_) if seats > 1 => {
+            return Err(Error::User(
+                "Only single transferable vote can elect more than one seat.".to_string(),
+            ));
+        }
+        Some(method) => method,
+    };
+
+    let score_range = {
+        let default = ScoreRange::default();
+        let min = match options.get("min-score") {
+            Some(ApplicationCommandInteractionDataOptionValue::Integer(i)) => *i,
+            _ => default.min,
+        };
+        let max = match options.get("max-score") {
+            Some(ApplicationCommandInteractionDataOptionValue::Integer(i)) => *i,
+            _ => default.max,
+        };
+        ScoreRange { min, max }
+    };
+
+    // Every possible score has to fit in a single select menu.
+    if poll_kind == PollKind::Score
+        && (score_range.min >= score_range.max || score_range.max - score_range.min >= 25)
+    {
+        return Err(Error::User(
+            "Score range must go from a lower to a higher score, with at most 25 values."
+                .to_string(),
+        ));
+    }
+
+    let closes_at = match (options.get("closes-in"), options.get("closes-at")) {
+        (Some(_), Some(_)) => {
+            return Err(Error::User(
+                "Give either closes-in or closes-at, not both.".to_string(),
+            ))
+        }
+        (Some(ApplicationCommandInteractionDataOptionValue::Integer(minutes)), _) => {
+            if *minutes < 1 {
+                return Err(Error::User(
+                    "closes-in must be at least 1 minute.".to_string(),
+                ));
+            }
+            match TimeDelta::try_minutes(*minutes).and_then(|d| Utc::now().checked_add_signed(d)) {
+                Some(closes_at) => Some(closes_at),
+                None => return Err(Error::User("closes-in is too far away.".to_string())),
+            }
+        }
+        (_, Some(ApplicationCommandInteractionDataOptionValue::String(s))) => {
+            match parse_closing_time(s) {
+                Some(closes_at) if closes_at > Utc::now() => Some(closes_at),
+                Some(_) => return Err(Error::User("closes-at must be in the future.".to_string())),
+                None => {
+                    return Err(Error::User(
+                        "closes-at must look like 2024-01-31 18:00 (UTC) or be RFC 3339."
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let change_policy = match options.get("vote-changes") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "locked" => {
+            ChangePolicy::Locked
+        }
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "timed" => {
+            match options.get("change-window") {
+                Some(ApplicationCommandInteractionDataOptionValue::Integer(minutes)) => {
+                    if !(1..=MAX_CHANGE_WINDOW).contains(minutes) {
+                        return Err(Error::User(format!(
+                            "change-window must be from 1 to {} minutes.",
+                            MAX_CHANGE_WINDOW
+                        )));
+                    }
+                    ChangePolicy::Timed(*minutes)
+                }
+                _ => {
+                    return Err(Error::User(
+                        "Give a change-window in minutes for timed vote changes.".to_string(),
+                    ))
+                }
+            }
+        }
+        _ => ChangePolicy::UntilClose,
+    };
+
+    let eligible_roles: BTreeSet<RoleId> = match options.get("eligible-role") {
+        Some(ApplicationCommandInteractionDataOptionValue::Role(role)) => {
+            std::iter::once(role.id).collect()
+        }
+        _ => BTreeSet::new(),
+    };
+    let eligible_users: BTreeSet<UserId> = match options.get("eligible-users") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => parse_user_ids(s)?,
+        _ => BTreeSet::new(),
+    };
+
+    let quorum = match options.get("quorum") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => match parse_quorum(s) {
+            Some(Quorum::Share(_)) if eligible_roles.is_empty() && eligible_users.is_empty() => {
+                return Err(Error::User(
+                    "A percentage quorum needs an eligible-role or eligible-users.".to_string(),
+                ))
+            }
+            Some(quorum) => Some(quorum),
+            None => {
+                return Err(Error::User(
+                    "quorum must be a number of responses, like 10, or a percentage, like 50%."
+                        .to_string(),
+                ))
+            }
+        },
+        _ => None,
+    };
+
+    let threshold = match options.get("threshold") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => match s.as_str() {
+            "two-thirds" => Some(Threshold::TwoThirds),
+            "unanimity" => Some(Threshold::Unanimity),
+            _ => Some(Threshold::SimpleMajority),
+        },
+        _ => None,
+    };
+
+    if threshold.is_some() && !matches!(poll_kind, PollKind::Plurality | PollKind::Approval) {
+        return Err(Error::User(
+            "A pass threshold can only be set on plurality or approval polls.".to_string(),
+        ));
+    }
+
+    let verifiable = matches!(
+        options.get("verifiable"),
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(true))
+    );
+
+    if verifiable && poll_kind != PollKind::Plurality {
+        return Err(Error::User(
+            "Only plurality polls can be verifiable.".to_string(),
+        ));
+    }
+
+    let trustees: Vec<UserId> = match options.get("trustees") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => {
+            parse_user_ids(s)?.into_iter().collect()
+        }
+        _ => Vec::new(),
+    };
+    let trustee_threshold = match options.get("trustee-threshold") {
+        Some(ApplicationCommandInteractionDataOptionValue::Integer(k)) => *k,
+        _ => trustees.len() as i64,
+    };
+
+    if !trustees.is_empty() {
+        let refusal = if poll_kind != PollKind::Plurality {
+            Some("Only plurality polls can be encrypted.".to_string())
+        } else if verifiable {
+            Some("A poll can be verifiable or encrypted, not both.".to_string())
+        } else if trustee_threshold < 1 || trustee_threshold as usize > trustees.len() {
+            Some(format!(
+                "trustee-threshold must be between 1 and the {} trustees.",
+                trustees.len()
+            ))
+        } else {
+            None
+        };
+        if let Some(refusal) = refusal {
+            return Err(Error::User(refusal));
+        }
+        // A share dealt to anyone who cannot submit it is lost, so check the trustees first.
+        for trustee in &trustees {
+            match trustee.to_user(&ctx.http).await {
+                Ok(user) if !user.bot => {}
+                Ok(_) => {
+                    return Err(Error::User(format!(
+                        "<@{}> is a bot and cannot be a trustee.",
+                        trustee
+                    )))
+                }
+                Err(_) => {
+                    return Err(Error::User(format!(
+                        "Could not find trustee <@{}>.",
+                        trustee
+                    )))
+                }
+            }
+        }
+    }
+
+    // The secret key is never stored: it only survives as the shares DMed to trustees below.
+    let (election, key_shares) = match trustees.is_empty() {
+        true => (None, Vec::new()),
+        false => {
+            let (public_key, key_shares) =
+                elgamal::deal(trustee_threshold as usize, trustees.len());
+            let election = Election {
+                public_key,
+                threshold: trustee_threshold as usize,
+                trustees: trustees
+                    .iter()
+                    .zip(&key_shares)
+                    .map(|(user, share)| (*user, share.verification_key()))
+                    .collect(),
+                partials: BTreeMap::new(),
+                tally: None,
+            };
+            (Some(election), key_shares)
+        }
+    };
+
+    let sealed = matches!(
+        options.get("sealed"),
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(true))
+    );
+
+    let allow_abstain = matches!(
+        options.get("allow-abstain"),
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(true))
+    );
+
+    let created = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        let poll_keys = data_read
+            .get::<PollKeys>()
+            .expect("Expected PollKeys in TypeMap.")
+            .clone();
+        let created = match poll_map.entry(poll_id.clone()) {
+            Occupied(_) => None,
+            Vacant(entry) => {
+                let key = data_read
+                    .get::<PollStorage>()
+                    .expect("Expected PollStorage in TypeMap.")
+                    .next_poll_key()?;
+                let poll = Poll {
+                    key,
+                    owner: owner.clone(),
+                    prompt: poll_prompt.clone(),
+                    created_at: Some(Utc::now()),
+                    closed_at: None,
+                    kind: poll_kind,
+                    options: poll_options.clone(),
+                    score_range: match poll_kind {
+                        PollKind::Score => Some(score_range),
+                        _ => None,
+                    },
+                    method: ranked_method,
+                    seats: match (poll_kind, ranked_method) {
+                        (PollKind::Ranked, RankedMethod::Stv) => Some(seats as usize),
+                        _ => None,
+                    },
+                    ballot_box: BallotBox::default(),
+                    open: true,
+                    message: None,
+                    closes_at,
+                    allow_abstain,
+                    change_policy,
+                    admins: BTreeSet::new(),
+                    admin_roles: BTreeSet::new(),
+                    guild_id: command.guild_id,
+                    eligible_roles: eligible_roles.clone(),
+                    eligible_users: eligible_users.clone(),
+                    quorum,
+                    threshold,
+                    board: match verifiable {
+                        true => Some(Board::new(poll_id)),
+                        false => None,
+                    },
+                    election: election.clone(),
+                    sealed,
+                    revealed: false,
+                };
+                save_poll(&data_read, poll_id, &poll)?;
+                poll_keys.insert(key, poll_id.clone());
+                Some(entry.insert(poll).clone())
+            }
+        };
+        created
+    };
+
+    if let Some(poll) = created {
+        let content = render_poll_message(&poll);
+
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(content);
+                        message.components(|components| {
+                            components.set_action_rows(create_poll_components(&poll))
+                        });
+                        message
+                    })
+            })
+            .await?;
+
+        // Remember where the poll was posted so it can be marked closed later.
+        match command.get_interaction_response(&ctx.http).await {
+            Ok(message) => {
+                let data_read = ctx.data.read().await;
+                let poll_map = data_read
+                    .get::<PollData>()
+                    .expect("Expected PollData in TypeMap.")
+                    .clone();
+                if let Some(mut entry) = poll_map.get_mut(poll_id) {
+                    update_poll(&data_read, poll_id, &mut entry, |poll| {
+                        poll.message = Some((message.channel_id, message.id));
+                        Ok(())
+                    })?;
+                };
+            }
+            Err(e) => println!("Failed to fetch message for poll {}: {}", poll_id, e),
+        }
+
+        let mut undelivered = Vec::new();
+        for (trustee, share) in trustees.iter().zip(&key_shares) {
+            let content = format!(
+                "You are trustee {} of {} for poll {}. Keep this key share secret. Once the poll \
+                 closes, submit it with /poll-trustee-share:\n`{}`",
+                share.index,
+                trustees.len(),
+                poll_id,
+                share
+            );
+            let sent = match trustee.create_dm_channel(&ctx.http).await {
+                Ok(channel) => channel
+                    .send_message(&ctx.http, |message| message.content(content))
+                    .await
+                    .map(|_| ()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                println!("Failed to send key share to trustee {}: {}", trustee, e);
+                undelivered.push(format!("<@{}>", trustee));
+            }
+        }
+        // Without enough shares the poll could never be decrypted, so it is not created after all.
+        if let Some(election) = &poll.election {
+            if trustees.len() - undelivered.len() < election.threshold {
+                remove_poll(&*ctx.data.read().await, poll_id)?;
+                if let Err(e) = command
+                    .delete_original_interaction_response(&ctx.http)
+                    .await
+                {
+                    println!("Failed to delete message of poll {}: {}", poll_id, e);
+                }
+                return Err(Error::User(format!(
+                    "Could not DM key shares to {}, leaving fewer than the {} trustees needed to \
+                     decrypt the results. The poll was not created.",
+                    undelivered.join(", "),
+                    election.threshold
+                )));
+            }
+        }
+        if !undelivered.is_empty() {
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message.content(format!(
+                        "Could not DM key shares to {}; their shares are lost.",
+                        undelivered.join(", ")
+                    ));
+                    message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    message
+                })
+                .await?;
+        }
+
+        Ok(())
+    } else {
+        Err(Error::User("Poll with that id already exists.".to_string()))
+    }
+}
+
+/// The number of users eligible to vote in a restricted poll, or `None` if anyone may vote or
+/// the number is unknown.
+///
+/// Counting role holders lists the guild's members, so needs the Server Members intent; if that
+/// fails the electorate is unknown, since a partial count would understate it.
+async fn electorate_size(ctx: &Context, poll: &Poll) -> Option<usize> {
+    if !poll.is_restricted() {
+        return None;
+    }
+    let mut electorate: BTreeSet<UserId> = poll.eligible_users.clone();
+    if !poll.eligible_roles.is_empty() {
+        let guild_id = poll.guild_id?;
+        let mut after = None;
+        loop {
+            match guild_id.members(&ctx.http, Some(1000), after).await {
+                Ok(members) => {
+                    for member in members.iter() {
+                        if member.roles.iter().any(|r| poll.eligible_roles.contains(r)) {
+                            electorate.insert(member.user.id);
+                        }
+                    }
+                    match members.last() {
+                        Some(last) if members.len() == 1000 => after = Some(last.user.id),
+                        _ => break,
+                    }
+                }
+                Err(e) => {
+                    println!("Failed to list members of guild {}: {}", guild_id, e);
+                    return None;
+                }
+            }
+        }
+    }
+    Some(electorate.len())
+}
+
+/// Parse a quorum as either a count of responses (`10`) or a percentage of the electorate (`50%`).
+fn parse_quorum(s: &str) -> Option<Quorum> {
+    let s = s.trim();
+    match s.strip_suffix('%') {
+        Some(percent) => match percent.trim().parse::<f64>() {
+            Ok(p) if p > 0.0 && p <= 100.0 => Some(Quorum::Share(p)),
+            _ => None,
+        },
+        None => s
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .map(Quorum::Absolute),
+    }
+}
+
+/// Parse a list of user mentions (`<@id>`) or ids, separated by spaces or commas. Anything else,
+/// such as a role mention, is refused rather than read as a user.
+fn parse_user_ids(s: &str) -> Result<BTreeSet<UserId>> {
+    s.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let id = match token.strip_prefix("<@").and_then(|t| t.strip_suffix('>')) {
+                Some(mention) => mention.strip_prefix('!').unwrap_or(mention),
+                None => token,
+            };
+            match id.parse::<u64>() {
+                Ok(n) if n > 0 && id.bytes().all(|b| b.is_ascii_digit()) => Ok(UserId(n)),
+                _ => Err(Error::User(format!("{} is not a user.", token))),
+            }
+        })
+        .collect()
+}
+
+/// Headline numbers for announcing a poll's results: a bar per option, and the winners.
+struct Standings {
+    /// What the bars measure, such as votes or first preferences.
+    measure: &'static str,
+    results: Vec<OptionResult>,
+    /// The value of a bar that fills the chart, counted from `origin`.
+    scale: f64,
+    origin: f64,
+    winners: Vec<String>,
+}
+
+/// One option's count, or its mean score in a score poll.
+#[derive(Serialize)]
+struct OptionResult {
+    option: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_score: Option<f64>,
+}
+
+impl Standings {
+    fn bars(&self) -> Vec<Bar> {
+        self.results
+            .iter()
+            .map(|result| match (result.count, result.mean_score) {
+                (Some(count), _) => Bar {
+                    label: result.option.clone(),
+                    value: count as f64,
+                    caption: format!("{} ({:.1}%)", count, result.percent.unwrap_or(0.0)),
+                },
+                (None, Some(mean)) => Bar {
+                    label: result.option.clone(),
+                    value: mean - self.origin,
+                    caption: format!("{:.2}", mean),
+                },
+                (None, None) => Bar {
+                    label: result.option.clone(),
+                    value: 0.0,
+                    caption: "none".to_string(),
+                },
+            })
+            .collect()
+    }
+
+    fn chart(&self, title: &str) -> Option<Vec<u8>> {
+        match chart::bar_chart(title, &self.bars(), self.scale) {
+            Ok(png) => Some(png),
+            Err(e) => {
+                println!("Failed to draw chart for {}: {}", title, e);
+                None
+            }
+        }
+    }
+}
+
+fn percent(n: usize, of: usize) -> f64 {
+    match of {
+        0 => 0.0,
+        _ => 100.0 * n as f64 / of as f64,
+    }
+}
+
+/// Options with the highest count, unless nobody voted.
+fn top_options(counts: &[(String, usize)]) -> Vec<String> {
+    let best = counts.iter().map(|(_, n)| *n).max().unwrap_or(0);
+    match best {
+        0 => Vec::new(),
+        _ => counts
+            .iter()
+            .filter(|(_, n)| *n == best)
+            .map(|(o, _)| o.clone())
+            .collect(),
+    }
+}
+
+/// `None` while the results are sealed or encrypted.
+fn standings(poll: &Poll) -> Option<Standings> {
+    if poll.sealed && !poll.revealed {
+        return None;
+    }
+    let ballots: Vec<&Ballot> = poll.ballot_box.ballots().collect();
+    let count_results = |counts: &[(String, usize)], counted: usize| {
+        counts
+            .iter()
+            .map(|(option, n)| OptionResult {
+                option: option.clone(),
+                count: Some(*n),
+                percent: Some(percent(*n, counted)),
+                mean_score: None,
+            })
+            .collect()
+    };
+
+    if let Some(election) = &poll.election {
+        let counts: Vec<(String, usize)> = poll
+            .options
+            .iter()
+            .cloned()
+            .zip(election.tally.as_ref()?.iter().map(|n| *n as usize))
+            .collect();
+        let counted = counts.iter().map(|(_, n)| n).sum();
+        return Some(Standings {
+            measure: "votes",
+            results: count_results(&counts, counted),
+            scale: counted as f64,
+            origin: 0.0,
+            winners: top_options(&counts),
+        });
+    }
+
+    match poll.kind {
+        PollKind::Plurality | PollKind::Approval => {
+            let mut counted = 0;
+            let mut counts: Vec<(String, usize)> =
+                poll.options.iter().map(|o| (o.clone(), 0)).collect();
+            for ballot in &ballots {
+                let voted_for = |option: &String| match ballot {
+                    Ballot::Choice(choice) => choice == option,
+                    Ballot::Approval { approved } => approved.contains(option),
+                    _ => false,
+                };
+                if counts.iter().any(|(o, _)| voted_for(o)) {
+                    counted += 1;
+                }
+                for (option, n) in counts.iter_mut() {
+                    if voted_for(option) {
+                        *n += 1;
+                    }
+                }
+            }
+            Some(Standings {
+                measure: "votes",
+                results: count_results(&counts, counted),
+                scale: counted as f64,
+                origin: 0.0,
+                winners: top_options(&counts),
+            })
+        }
+        PollKind::Ranked => {
+            let rankings: Vec<&[String]> = ballots
+                .iter()
+                .filter_map(|ballot| match ballot {
+                    Ballot::Ranking(ranking) => Some(ranking.as_slice()),
+                    _ => None,
+                })
+                .collect();
+            let counts: Vec<(String, usize)> = poll
+                .options
+                .iter()
+                .map(|o| {
+                    let first = rankings.iter().filter(|r| r.first() == Some(o)).count();
+                    (o.clone(), first)
+                })
+                .collect();
+            let winners = match poll.method {
+                RankedMethod::InstantRunoff => instant_runoff(&poll.options, &rankings)
+                    .1
+                    .into_iter()
+                    .collect(),
+                RankedMethod::Schulze => schulze(&poll.options, &rankings)
+                    .winners
+                    .iter()
+                    .map(|i| poll.options[*i].clone())
+                    .collect(),
+                RankedMethod::RankedPairs => ranked_pairs(&poll.options, &rankings)
+                    .winner
+                    .map(|i| poll.options[i].clone())
+                    .into_iter()
+                    .collect(),
+                RankedMethod::Stv => {
+                    single_transferable_vote(&poll.options, &rankings, poll.seats.unwrap_or(1))
+                        .elected
+                }
+            };
+            Some(Standings {
+                measure: "first preferences",
+                results: count_results(&counts, rankings.len()),
+                scale: rankings.len() as f64,
+                origin: 0.0,
+                winners,
+            })
+        }
+        PollKind::Score => {
+            let means: Vec<(String, Option<f64>)> = poll
+                .options
+                .iter()
+                .map(|o| {
+                    let scores: Vec<i64> = ballots
+                        .iter()
+                        .filter_map(|ballot| match ballot {
+                            Ballot::Scores { scores } => scores.get(o).copied(),
+                            _ => None,
+                        })
+                        .collect();
+                    let mean = match scores.len() {
+                        0 => None,
+                        n => Some(scores.iter().sum::<i64>() as f64 / n as f64),
+                    };
+                    (o.clone(), mean)
+                })
+                .collect();
+            let best = means.iter().filter_map(|(_, m)| *m).reduce(f64::max);
+            let range = poll.score_range.unwrap_or_default();
+            Some(Standings {
+                measure: "mean scores",
+                results: means
+                    .iter()
+                    .map(|(o, mean)| OptionResult {
+                        option: o.clone(),
+                        count: None,
+                        percent: None,
+                        mean_score: *mean,
+                    })
+                    .collect(),
+                // Bars start from the lowest possible score.
+                scale: (range.max - range.min) as f64,
+                origin: range.min as f64,
+                winners: means
+                    .iter()
+                    .filter(|(_, m)| m.is_some() && *m == best)
+                    .map(|(o, _)| o.clone())
+                    .collect(),
+            })
+        }
+    }
+}
+
+async fn send_results(ctx: &Context, poll_id: &String, poll: &Poll, to: &User) -> Result<()> {
+    let electorate = electorate_size(ctx, poll).await;
+    let report = poll_report(poll_id, poll, electorate);
+    let chart = standings(poll).and_then(|s| s.chart(&format!("Results for poll {}", poll_id)));
+    let chart_name = format!("{}-results.png", poll_id);
+    let channel = to.create_dm_channel(&ctx.http).await?;
+    channel
+        .send_message(&ctx.http, |message| {
+            message.content(report);
+            if let Some(chart) = &chart {
+                message.add_file((chart.as_slice(), chart_name.as_str()));
+            }
+            message
+        })
+        .await?;
+    Ok(())
+}
+
+/// Re-render a poll's public message and buttons from the poll.
+async fn refresh_poll_message(ctx: &Context, poll: &Poll) -> Result<()> {
+    if let Some((channel_id, message_id)) = poll.message {
+        channel_id
+            .edit_message(&ctx.http, message_id, |message| {
+                message.content(render_poll_message(poll));
+                message.components(|components| {
+                    components.set_action_rows(create_poll_components(poll))
+                });
+                message
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+async fn close_poll_message(ctx: &Context, poll_id: &String, poll: &Poll) -> Result<()> {
+    refresh_poll_message(ctx, poll).await?;
+    if let Some((channel_id, _)) = poll.message {
+        if let Some((json, filename)) = proof_file(poll) {
+            channel_id
+                .send_message(&ctx.http, |message| {
+                    message.content(format!(
+                        "Verifiable tally for poll {}. Check it with \
+                         `secret-ballot-bot verify {}`.",
+                        poll_id, filename
+                    ));
+                    message.add_file((json.as_slice(), filename.as_str()));
+                    message
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Close every open poll whose scheduled closing time has passed, then send its owner the results.
+async fn close_due_polls(ctx: &Context) {
+    let now = Utc::now();
+    let due: Vec<(String, Poll)> = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        let due = poll_map
+            .iter_mut()
+            .filter_map(|mut kv| match kv.closes_at {
+                Some(closes_at) if kv.open && closes_at <= now => {
+                    let poll_id = kv.key().clone();
+                    // A poll that could not be saved stays open and is tried again next time.
+                    match update_poll(&data_read, &poll_id, &mut kv, |poll| {
+                        poll.close();
+                        Ok(())
+                    }) {
+                        Ok(()) => Some((poll_id, kv.value().clone())),
+                        Err(e) => {
+                            println!("Failed to save poll {}: {}", poll_id, e);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            })
+            .collect();
+        due
+    };
+
+    for (poll_id, poll) in due {
+        println!("Closing poll '{}' on schedule", poll_id);
+        if let Err(e) = close_poll_message(ctx, &poll_id, &poll).await {
+            println!("Failed to update closed poll {}: {}", poll_id, e);
+        }
+        if let Err(e) = send_results(ctx, &poll_id, &poll, &poll.owner).await {
+            println!("Failed to send results for poll {}: {}", poll_id, e);
+        }
+    }
+}
+
+async fn run_scheduler(ctx: Context) {
+    let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+    loop {
+        interval.tick().await;
+        close_due_polls(&ctx).await;
+    }
+}
+
+/// Parse a closing time given either as RFC 3339 or as "YYYY-MM-DD HH:MM" in UTC.
+fn parse_closing_time(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s.trim())
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(s.trim(), "%Y-%m-%d %H:%M").map(|t| t.and_utc()))
+        .ok()
+}
+
+async fn handle_poll_results(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let content = {
+        if let Some(poll) = {
+            let data_read = ctx.data.read().await;
+            let poll_map = data_read
+                .get::<PollData>()
+                .expect("Expected PollData in TypeMap.")
+                .clone();
+            poll_map.get(poll_id).map(|kv| kv.value().clone())
+        } {
+            if poll.is_admin(user, command.member.as_ref()) {
+                send_results(ctx, poll_id, &poll, user).await?;
+                "Results sent by direct message."
+            } else {
+                return Err(not_admin());
+            }
+        } else {
+            return Err(no_such_poll());
+        }
+    };
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content);
+                    message
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    id: &'a str,
+    prompt: &'a str,
+    kind: PollKind,
+    options: &'a [String],
+    created_at: Option<DateTime<Utc>>,
+    closes_at: Option<DateTime<Utc>>,
+    closed_at: Option<DateTime<Utc>>,
+    turnout: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eligible: Option<usize>,
+    results: &'a [OptionResult],
+    /// Ranked and score ballots, in no particular order and without voters.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ballots: Vec<&'a Ballot>,
+}
+
+/// Quotes a field if needed. Fields that a spreadsheet would read as a formula get a leading `'`,
+/// since prompts, options and names are all user text; numbers, negative scores included, are
+/// left as they are.
+fn csv_field(field: &str) -> String {
+    let formula = field.starts_with(['=', '+', '-', '@', '\t', '\r']);
+    let field = if formula && field.parse::<f64>().is_err() {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f.as_ref())).collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// The export as CSV: metadata rows, then a row per option, then a row per ballot.
+fn export_csv(export: &Export) -> String {
+    let timestamp = |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339()).unwrap_or_default();
+    let mut csv = String::new();
+    csv.push_str(&csv_row(&["id", export.id]));
+    csv.push_str(&csv_row(&["prompt", export.prompt]));
+    csv.push_str(&csv_row(&[
+        "kind",
+        &serde_json::to_value(export.kind)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default(),
+    ]));
+    csv.push_str(&csv_row(&["created_at", &timestamp(export.created_at)]));
+    csv.push_str(&csv_row(&["closes_at", &timestamp(export.closes_at)]));
+    csv.push_str(&csv_row(&["closed_at", &timestamp(export.closed_at)]));
+    csv.push_str(&csv_row(&["turnout", &export.turnout.to_string()]));
+    if let Some(eligible) = export.eligible {
+        csv.push_str(&csv_row(&["eligible", &eligible.to_string()]));
+    }
+
+    csv.push('\n');
+    csv.push_str(&csv_row(&["option", "count", "percent", "mean_score"]));
+    for result in export.results {
+        let number = |n: Option<f64>| n.map(|n| format!("{:.2}", n)).unwrap_or_default();
+        csv.push_str(&csv_row(&[
+            result.option.clone(),
+            result.count.map(|c| c.to_string()).unwrap_or_default(),
+            number(result.percent),
+            number(result.mean_score),
+        ]));
+    }
+
+    if !export.ballots.is_empty() {
+        csv.push('\n');
+        match export.kind {
+            PollKind::Score => {
+                let mut header = vec!["ballot".to_string()];
+                header.extend(export.options.iter().cloned());
+                csv.push_str(&csv_row(&header));
+            }
+            _ => {
+                let mut header = vec!["ballot".to_string()];
+                header.extend((1..=export.options.len()).map(|i| format!("rank {}", i)));
+                csv.push_str(&csv_row(&header));
+            }
+        }
+        for (i, ballot) in export.ballots.iter().enumerate() {
+            let mut row = vec![(i + 1).to_string()];
+            match ballot {
+                Ballot::Ranking(ranking) => row.extend(ranking.iter().cloned()),
+                Ballot::Scores { scores } => row.extend(
+                    export
+                        .options
+                        .iter()
+                        .map(|o| scores.get(o).map(|s| s.to_string()).unwrap_or_default()),
+                ),
+                _ => {}
+            }
+            csv.push_str(&csv_row(&row));
+        }
+    }
+    csv
+}
+
+async fn handle_poll_export(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+    let json = matches!(
+        options.get("format"),
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) if s == "json"
+    );
+
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        poll_map.get(poll_id).map(|kv| kv.value().clone())
+    };
+
+    let content = match poll {
+        None => return Err(no_such_poll()),
+        Some(poll) if !poll.is_admin(user, command.member.as_ref()) => return Err(not_admin()),
+        Some(poll) => match standings(&poll) {
+            None => return Err(still_sealed()),
+            Some(standings) => {
+                let export = Export {
+                    id: poll_id,
+                    prompt: &poll.prompt,
+                    kind: poll.kind,
+                    options: &poll.options,
+                    created_at: poll.created_at,
+                    closes_at: poll.closes_at,
+                    closed_at: poll.closed_at,
+                    turnout: poll.ballot_box.len(),
+                    eligible: electorate_size(ctx, &poll).await,
+                    results: &standings.results,
+                    ballots: poll
+                        .ballot_box
+                        .ballots()
+                        .filter(|b| matches!(b, Ballot::Ranking(_) | Ballot::Scores { .. }))
+                        .collect(),
+                };
+                let (data, filename) = match json {
+                    true => (
+                        serde_json::to_vec_pretty(&export).unwrap_or_default(),
+                        format!("{}.json", poll_id),
+                    ),
+                    false => (export_csv(&export).into_bytes(), format!("{}.csv", poll_id)),
+                };
+                let channel = user.create_dm_channel(&ctx.http).await?;
+                channel
+                    .send_message(&ctx.http, |message| {
+                        message.content(format!("Export of poll {}", poll_id));
+                        message.add_file((data.as_slice(), filename.as_str()));
+                        message
+                    })
+                    .await?;
+                "Export sent by direct message.".to_string()
+            }
+        },
+    };
+
+    reply_to_command_ephemeral(ctx, command, &content).await
+}
+
+async fn handle_poll_close(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let content = {
+        if let Some(poll) = {
+            let data_read = ctx.data.read().await;
+            let poll_map = data_read
+                .get::<PollData>()
+                .expect("Expected PollData in TypeMap.")
+                .clone();
+            poll_map.get(poll_id).map(|kv| kv.value().clone())
+        } {
+            if poll.is_admin(user, command.member.as_ref()) {
+                let data_read = ctx.data.read().await;
+                let poll_map = data_read
+                    .get::<PollData>()
+                    .expect("Expected PollData in TypeMap.")
+                    .clone();
+                let closed = match poll_map.get_mut(poll_id) {
+                    Some(mut entry) => {
+                        update_poll(&data_read, poll_id, &mut entry, |poll| {
+                            poll.close();
+                            Ok(())
+                        })?;
+                        Some(entry.clone())
+                    }
+                    None => None,
+                };
+                if let Some(poll) = closed {
+                    if let Err(e) = close_poll_message(ctx, poll_id, &poll).await {
+                        println!("Failed to update closed poll {}: {}", poll_id, e);
+                    }
+                }
+                "Poll closed."
+            } else {
+                return Err(not_admin());
+            }
+        } else {
+            return Err(no_such_poll());
+        }
+    };
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content);
+                    message
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn handle_poll_reveal(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        poll_map.get(poll_id).map(|kv| kv.value().clone())
+    };
+
+    let content = match poll {
+        None => return Err(no_such_poll()),
+        Some(poll) if !poll.is_admin(user, command.member.as_ref()) => return Err(not_admin()),
+        Some(poll) if !poll.sealed => {
+            return Err(Error::User(
+                "That poll is not sealed; use /poll-results instead.".to_string(),
+            ))
+        }
+        Some(poll) if poll.revealed => {
+            return Err(Error::User(
+                "That poll has already been revealed.".to_string(),
+            ))
+        }
+        Some(_) => {
+            // Revealing ends the commit phase: the poll closes and its tally goes public at once.
+            let poll = {
+                let data_read = ctx.data.read().await;
+                let poll_map = data_read
+                    .get::<PollData>()
+                    .expect("Expected PollData in TypeMap.")
+                    .clone();
+                let mut entry = poll_map.get_mut(poll_id).ok_or_else(no_such_poll)?;
+                update_poll(&data_read, poll_id, &mut entry, |poll| {
+                    poll.close();
+                    poll.revealed = true;
+                    Ok(())
+                })?;
+                entry.clone()
+            };
+            if let Err(e) = close_poll_message(ctx, poll_id, &poll).await {
+                println!("Failed to update closed poll {}: {}", poll_id, e);
+            }
+            let electorate = electorate_size(ctx, &poll).await;
+            let report = poll_report(poll_id, &poll, electorate);
+            let chart =
+                standings(&poll).and_then(|s| s.chart(&format!("Results for poll {}", poll_id)));
+            let chart_name = format!("{}-results.png", poll_id);
+            let channel_id = match poll.message {
+                Some((channel_id, _)) => channel_id,
+                None => command.channel_id,
+            };
+            channel_id
+                .send_message(&ctx.http, |message| {
+                    message.content(report);
+                    if let Some(chart) = &chart {
+                        message.add_file((chart.as_slice(), chart_name.as_str()));
+                    }
+                    message
+                })
+                .await?;
+            "Results revealed."
+        }
+    };
+
+    reply_to_command_ephemeral(ctx, command, &content.to_string()).await
+}
+
+async fn handle_poll_publish(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+    let force = matches!(
+        options.get("force"),
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(true))
+    );
+    let reply = matches!(
+        options.get("reply"),
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(true))
+    );
+
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        poll_map.get(poll_id).map(|kv| kv.value().clone())
+    };
+
+    let content = match poll {
+        None => return Err(no_such_poll()),
+        Some(poll) if !poll.is_admin(user, command.member.as_ref()) => return Err(not_admin()),
+        Some(poll) if poll.open && !force => {
+            return Err(Error::User(
+                "This poll is still open. Close it first, or publish anyway with force."
+                    .to_string(),
+            ))
+        }
+        Some(poll) => match standings(&poll) {
+            Some(standings) => {
+                let electorate = electorate_size(ctx, &poll).await;
+                let headline = match standings.winners.as_slice() {
+                    [] => "No winner.".to_string(),
+                    [winner] => format!("Winner: **{}**", winner),
+                    winners if poll.method == RankedMethod::Stv => {
+                        format!("Elected: **{}**", winners.join(", "))
+                    }
+                    winners => format!("Tied: **{}**", winners.join(", ")),
+                };
+                let description = format!("{}{}", headline, governance_report(&poll, electorate));
+                let footer = format!(
+                    "{} responses{}. Showing {}.",
+                    poll.ballot_box.len(),
+                    if poll.open {
+                        " so far; the poll is still open"
+                    } else {
+                        ""
+                    },
+                    standings.measure
+                );
+                let chart_name = format!("{}-results.png", poll_id);
+                let chart = standings.chart(&format!("Results for poll {}", poll_id));
+                let channel_id = match poll.message {
+                    Some((channel_id, _)) => channel_id,
+                    None => command.channel_id,
+                };
+                channel_id
+                    .send_message(&ctx.http, |message| {
+                        message.embed(|embed| {
+                            embed.title(format!("Results for poll {}", poll_id));
+                            embed.description(description);
+                            for bar in standings.bars().iter() {
+                                embed.field(&bar.label, &bar.caption, false);
+                            }
+                            if chart.is_some() {
+                                embed.attachment(&chart_name);
+                            }
+                            embed.footer(|f| f.text(footer));
+                            embed
+                        });
+                        if let Some(chart) = &chart {
+                            message.add_file((chart.as_slice(), chart_name.as_str()));
+                        }
+                        if let (true, Some(location)) = (reply, poll.message) {
+                            message.reference_message(location);
+                        }
+                        message
+                    })
+                    .await?;
+                "Results published.".to_string()
+            }
+            None => return Err(still_sealed()),
+        },
+    };
+
+    reply_to_command_ephemeral(ctx, command, &content).await
+}
+
+async fn handle_poll_delete(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let content = {
+        if let Some(poll) = {
+            let data_read = ctx.data.read().await;
+            let poll_map = data_read
+                .get::<PollData>()
+                .expect("Expected PollData in TypeMap.")
+                .clone();
+            poll_map.get(poll_id).map(|kv| kv.value().clone())
+        } {
+            if poll.is_admin(user, command.member.as_ref()) {
+                remove_poll(&*ctx.data.read().await, poll_id)?;
+                // Its controls could only say that the poll is gone.
+                if let Some((channel_id, message_id)) = poll.message {
+                    if let Err(e) = channel_id
+                        .edit_message(&ctx.http, message_id, |message| {
+                            message.components(|components| components)
+                        })
+                        .await
+                    {
+                        println!("Failed to remove controls of poll {}: {}", poll_id, e);
+                    }
+                }
+                "Poll deleted."
+            } else {
+                return Err(not_admin());
+            }
         } else {
-            "No poll with that ID."
+            return Err(no_such_poll());
+        }
+    };
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content);
+                    message
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn handle_poll_admin(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let user: &User = &command.user;
+
+    let subcommand = match command.data.options.first() {
+        Some(subcommand) => subcommand,
+        None => return handle_default(ctx, command).await,
+    };
+    let adding = subcommand.name == "add";
+
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = subcommand
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let target_user = match options.get("user") {
+        Some(ApplicationCommandInteractionDataOptionValue::User(u, _)) => Some(u.clone()),
+        _ => None,
+    };
+    let target_role = match options.get("role") {
+        Some(ApplicationCommandInteractionDataOptionValue::Role(r)) => Some(r.clone()),
+        _ => None,
+    };
+
+    if target_user.is_none() && target_role.is_none() {
+        return Err(Error::User("Give a user or a role.".to_string()));
+    }
+
+    let updated = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        let updated = match poll_map.get_mut(poll_id) {
+            Some(mut entry) if entry.is_admin(user, command.member.as_ref()) => {
+                update_poll(&data_read, poll_id, &mut entry, |poll| {
+                    if let Some(target) = &target_user {
+                        if adding {
+                            poll.admins.insert(target.id);
+                        } else {
+                            poll.admins.remove(&target.id);
+                        }
+                    }
+                    if let Some(target) = &target_role {
+                        if adding {
+                            poll.admin_roles.insert(target.id);
+                        } else {
+                            poll.admin_roles.remove(&target.id);
+                        }
+                    }
+                    Ok(())
+                })?;
+                Some(true)
+            }
+            Some(_) => Some(false),
+            None => None,
+        };
+        updated
+    };
+
+    let content = match updated {
+        Some(true) => {
+            let targets: Vec<String> = target_user
+                .iter()
+                .map(|u| u.tag())
+                .chain(target_role.iter().map(|r| format!("@{}", r.name)))
+                .collect();
+            if adding {
+                format!("Added {} as administrators.", targets.join(" and "))
+            } else {
+                format!("Removed {} as administrators.", targets.join(" and "))
+            }
         }
+        Some(false) => return Err(not_admin()),
+        None => return Err(no_such_poll()),
+    };
+
+    reply_to_command(ctx, command, &content).await
+}
+
+async fn handle_poll_board(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+
+    let board = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        poll_map.get(poll_id).map(|kv| kv.board.clone())
+    };
+
+    let board = match board {
+        Some(Some(board)) => board,
+        Some(None) => return Err(Error::User("That poll is not verifiable.".to_string())),
+        None => return Err(no_such_poll()),
     };
 
+    reply_to_command_ephemeral(
+        ctx,
+        command,
+        &format!(
+            "Bulletin board for poll {}: {} entries, head `{}`",
+            poll_id,
+            board.entries.len(),
+            board.head()
+        ),
+    )
+    .await?;
+
+    let json = serde_json::to_vec_pretty(&board).unwrap_or_default();
     command
-        .create_interaction_response(&ctx.http, |response| {
-            response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.content(content);
-                    message
-                })
+        .create_followup_message(&ctx.http, |message| {
+            message.add_file((json.as_slice(), format!("{}-board.json", poll_id).as_str()));
+            message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+            message
         })
-        .await
+        .await?;
+    Ok(())
 }
 
-async fn handle_poll_close(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
+async fn handle_poll_trustee_share(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> Result<()> {
+    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+        .data
+        .options
+        .iter()
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
+        .collect();
+
+    let poll_id = string_option(&options, "id")?;
+    let share: Option<KeyShare> = match options.get("share") {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => s.parse().ok(),
+        _ => None,
+    };
+
+    let (content, decrypted) = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        let mut entry = poll_map.get_mut(poll_id).ok_or_else(no_such_poll)?;
+        let content = update_poll(&data_read, poll_id, &mut entry, |poll| {
+            let voters = poll.ballot_box.len() as u64;
+            let sums = poll.encrypted_sums();
+            let open = poll.open;
+            let election = poll
+                .election
+                .as_mut()
+                .ok_or_else(|| Error::User("That poll is not encrypted.".to_string()))?;
+            if open {
+                return Err(Error::User(
+                    "Key shares can only be submitted once the poll is closed.".to_string(),
+                ));
+            }
+            if election.tally.is_some() {
+                return Err(Error::User(
+                    "That poll has already been decrypted.".to_string(),
+                ));
+            }
+            let share =
+                share.ok_or_else(|| Error::User("That is not a valid key share.".to_string()))?;
+            match election.trustees.get(share.index as usize - 1) {
+                Some((trustee, key))
+                    if *trustee == command.user.id && share.verification_key() == *key => {}
+                _ => {
+                    return Err(Error::Permission(
+                        "That key share does not belong to you for this poll.".to_string(),
+                    ))
+                }
+            }
+            election.partials.insert(
+                share.index,
+                sums.iter().map(|sum| share.partial_decrypt(sum)).collect(),
+            );
+            if election.partials.len() >= election.threshold {
+                election.tally = sums
+                    .iter()
+                    .enumerate()
+                    .map(|(i, sum)| {
+                        let partials: Vec<(u32, Point)> = election
+                            .partials
+                            .iter()
+                            .map(|(index, partial)| (*index, partial[i]))
+                            .collect();
+                        elgamal::combine(&partials, sum, voters)
+                    })
+                    .collect();
+            }
+            Ok(match election.tally {
+                Some(_) => "Share accepted. The results are now decrypted.".to_string(),
+                None => format!(
+                    "Share accepted ({} of {} needed).",
+                    election.partials.len(),
+                    election.threshold
+                ),
+            })
+        })?;
+        let decrypted = entry
+            .election
+            .as_ref()
+            .is_some_and(|election| election.tally.is_some())
+            .then(|| entry.clone());
+        (content, decrypted)
+    };
+
+    reply_to_command_ephemeral(ctx, command, &content).await?;
+    if let Some(poll) = decrypted {
+        send_results(ctx, poll_id, &poll, &poll.owner).await?;
+    }
+    Ok(())
+}
+
+async fn handle_poll_my_vote(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
     let user: &User = &command.user;
 
     let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
         .data
         .options
         .iter()
-        .filter_map(|o| match &o.resolved {
-            Some(v) => Some((o.name.clone(), v.clone())),
-            _ => None,
-        })
+        .filter_map(|o| o.resolved.as_ref().map(|v| (o.name.clone(), v.clone())))
         .collect();
 
-    let poll_id = match options.get("id").expect("expected poll id") {
-        ApplicationCommandInteractionDataOptionValue::String(s) => s,
-        _ => panic!("poll id must be String"),
-    };
+    let poll_id = string_option(&options, "id")?;
 
+    let voter = voter(ctx, poll_id, user.id).await;
     let content = {
-        if let Some(owner) = {
+        if let Some(poll) = {
             let data_read = ctx.data.read().await;
             let poll_map = data_read
                 .get::<PollData>()
                 .expect("Expected PollData in TypeMap.")
                 .clone();
-            poll_map.get(poll_id).map(|kv| kv.value().owner.clone())
+            poll_map.get(poll_id).map(|kv| kv.value().clone())
         } {
-            if user == &owner {
-                let data_read = ctx.data.read().await;
-                let poll_map = data_read
-                    .get::<PollData>()
-                    .expect("Expected PollData in TypeMap.")
-                    .clone();
-                poll_map
-                    .entry(poll_id.clone())
-                    .and_modify(|poll| poll.open = false);
-                "Poll closed."
-            } else {
-                "Not an owner of this poll."
+            match poll.ballot_box.get(&voter) {
+                Some(ballot) => describe_ballot(&poll, ballot),
+                None => "You have not voted in this poll.".to_string(),
             }
         } else {
-            "No poll with that ID."
+            return Err(no_such_poll());
         }
     };
 
-    command
+    reply_to_command_ephemeral(ctx, command, &content).await
+}
+
+async fn handle_default(_ctx: &Context, _command: &ApplicationCommandInteraction) -> Result<()> {
+    Err(Error::User("Unimplemented command".to_string()))
+}
+
+async fn handle_application_command(ctx: &Context, command: &ApplicationCommandInteraction) {
+    let command_name = command.data.name.as_str();
+    println!(
+        "Running command '{}' invoked by '{}'",
+        command_name,
+        command.user.tag()
+    );
+
+    increment_command(ctx, command_name).await;
+
+    if let Err(error) = match command_name {
+        "poll-new" => handle_poll_new(ctx, command).await,
+        "poll-results" => handle_poll_results(ctx, command).await,
+        "poll-close" => handle_poll_close(ctx, command).await,
+        "poll-reveal" => handle_poll_reveal(ctx, command).await,
+        "poll-publish" => handle_poll_publish(ctx, command).await,
+        "poll-export" => handle_poll_export(ctx, command).await,
+        "poll-delete" => handle_poll_delete(ctx, command).await,
+        "poll-my-vote" => handle_poll_my_vote(ctx, command).await,
+        "poll-board" => handle_poll_board(ctx, command).await,
+        "poll-trustee-share" => handle_poll_trustee_share(ctx, command).await,
+        "poll-admin" => handle_poll_admin(ctx, command).await,
+        _ => handle_default(ctx, command).await,
+    } {
+        respond_to_command_error(ctx, command, &error).await;
+    }
+}
+
+/// Log a failed interaction as a single line of `key=value` pairs.
+fn log_error(interaction: &str, name: &str, user: &User, error: &Error) {
+    println!(
+        "interaction={} name={:?} user={} kind={} error={:?}",
+        interaction,
+        name,
+        user.id,
+        error.kind(),
+        error.to_string()
+    );
+}
+
+/// Log why a command failed and privately tell whoever ran it. A command that had already been
+/// answered when it failed gets the explanation as a followup instead.
+async fn respond_to_command_error(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    error: &Error,
+) {
+    log_error("command", &command.data.name, &command.user, error);
+    let content = error.reply();
+    let sent = match reply_to_command_ephemeral(ctx, command, &content).await {
+        Ok(()) => Ok(()),
+        Err(_) => command
+            .create_followup_message(&ctx.http, |message| {
+                message.content(&content);
+                message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                message
+            })
+            .await
+            .map(|_| ()),
+    };
+    if let Err(e) = sent {
+        println!("Failed to report error to {}: {}", command.user.tag(), e);
+    }
+}
+
+/// The same as `respond_to_command_error`, for buttons and select menus.
+async fn respond_to_component_error(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    error: &Error,
+) {
+    log_error(
+        "component",
+        &component.data.custom_id,
+        &component.user,
+        error,
+    );
+    let content = error.reply();
+    let sent = match reply_ephemeral(ctx, component, &content).await {
+        Ok(()) => Ok(()),
+        Err(_) => component
+            .create_followup_message(&ctx.http, |message| {
+                message.content(&content);
+                message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                message
+            })
+            .await
+            .map(|_| ()),
+    };
+    if let Err(e) = sent {
+        println!("Failed to report error to {}: {}", component.user.tag(), e);
+    }
+}
+
+async fn handle_poll_response(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    poll_id: String,
+    option: Option<usize>,
+) -> Result<()> {
+    let voter = voter(ctx, &poll_id, component.user.id).await;
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+
+        let mut entry = poll_map.get_mut(&poll_id).ok_or_else(no_such_poll)?;
+        update_poll(&data_read, &poll_id, &mut entry, |poll| {
+            if !poll.open {
+                return Err(Error::User("This poll is closed.".to_string()));
+            }
+            let choice = option
+                .and_then(|i| poll.options.get(i).cloned())
+                .ok_or_else(|| Error::User("That option is not part of this poll.".to_string()))?;
+            poll.cast(
+                &voter,
+                &component.user,
+                component.member.as_ref(),
+                Some(Ballot::Choice(choice)),
+            )
+            .map_err(Error::User)?;
+            poll.message
+                .get_or_insert((component.channel_id, component.message.id));
+            Ok(())
+        })?;
+        entry.clone()
+    };
+
+    component
         .create_interaction_response(&ctx.http, |response| {
             response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .kind(InteractionResponseType::UpdateMessage)
                 .interaction_response_data(|message| {
-                    message.content(content);
+                    message.content(render_poll_message(&poll));
                     message
                 })
         })
-        .await
+        .await?;
+
+    confirm_vote(ctx, component, &voter, &poll, "click another option").await
 }
 
-async fn handle_poll_delete(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
-    let user: &User = &command.user;
+/// Privately tell a voter what the poll now holds for them, after the public count is updated.
+async fn confirm_vote(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    voter: &Voter,
+    poll: &Poll,
+    how_to_change: &str,
+) -> Result<()> {
+    let content = match poll.ballot_box.get(voter) {
+        Some(ballot) => {
+            let hint = match (poll.change_policy, poll.change_deadline(voter)) {
+                (ChangePolicy::Locked, _) => "Your vote is final.".to_string(),
+                (ChangePolicy::Timed(_), Some(deadline)) if deadline > Utc::now() => format!(
+                    "To change your vote, {} before <t:{}:t>.",
+                    how_to_change,
+                    deadline.timestamp()
+                ),
+                (ChangePolicy::Timed(_), _) => "Your vote can no longer be changed.".to_string(),
+                (ChangePolicy::UntilClose, _) => format!("To change your vote, {}.", how_to_change),
+            };
+            format!("{}\n{}", describe_ballot(poll, ballot), hint)
+        }
+        None => "You no longer have a vote in this poll.".to_string(),
+    };
+    component
+        .create_followup_message(&ctx.http, |message| {
+            message.content(content);
+            message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+            message
+        })
+        .await?;
+    send_receipt(ctx, &component.user, voter, poll).await
+}
 
-    let options: HashMap<String, ApplicationCommandInteractionDataOptionValue> = command
+/// DM a voter in a verifiable poll the opening of their commitment on the bulletin board.
+async fn send_receipt(ctx: &Context, user: &User, voter: &Voter, poll: &Poll) -> Result<()> {
+    let board = match &poll.board {
+        Some(board) => board,
+        None => return Ok(()),
+    };
+    let content = match poll.ballot_box.receipt(voter) {
+        Some(opening) => format!(
+            "Receipt for poll {}: {}\nCommitment: `{}`\nNonce: `{}`\nBoard head: `{}`\n\
+             Check your commitment is on the board with /poll-board, and once the poll closes \
+             run `secret-ballot-bot verify <proof file> {}`.",
+            board.poll_id,
+            match &opening.choice {
+                Some(choice) => format!("you voted for {}", choice),
+                None => "you abstained".to_string(),
+            },
+            opening.commitment,
+            opening.nonce,
+            board.head(),
+            opening.commitment
+        ),
+        None => format!(
+            "Your vote in poll {} was withdrawn and its commitment revoked.\nBoard head: `{}`",
+            board.poll_id,
+            board.head()
+        ),
+    };
+    user.direct_message(&ctx.http, |message| message.content(content))
+        .await?;
+    Ok(())
+}
+
+/// The proof for a verifiable poll, as a JSON file named after the poll. It holds the tally, so a
+/// sealed poll has none until it is revealed.
+fn proof_file(poll: &Poll) -> Option<(Vec<u8>, String)> {
+    if poll.sealed && !poll.revealed {
+        return None;
+    }
+    let board = poll.board.as_ref()?;
+    let openings: Vec<&Opening> = poll.ballot_box.receipts().collect();
+    let proof = Proof::new(board, &poll.options, &openings);
+    match serde_json::to_vec_pretty(&proof) {
+        Ok(json) => Some((json, format!("{}-proof.json", board.poll_id))),
+        Err(e) => {
+            println!("Failed to serialize proof for {}: {}", board.poll_id, e);
+            None
+        }
+    }
+}
+
+/// Withdraw the voter's ballot, or replace it with an abstention.
+async fn handle_vote_control(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    poll_id: &str,
+    abstain: bool,
+) -> Result<()> {
+    let voter = voter(ctx, poll_id, component.user.id).await;
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+
+        let mut entry = poll_map.get_mut(poll_id).ok_or_else(no_such_poll)?;
+        update_poll(&data_read, poll_id, &mut entry, |poll| {
+            if !poll.open {
+                return Err(Error::User("This poll is closed.".to_string()));
+            }
+            if abstain && !poll.allow_abstain {
+                return Err(Error::User(
+                    "This poll does not allow abstaining.".to_string(),
+                ));
+            }
+            let ballot = if abstain { Some(Ballot::Abstain) } else { None };
+            poll.cast(&voter, &component.user, component.member.as_ref(), ballot)
+                .map_err(Error::User)
+        })?;
+        entry.clone()
+    };
+
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.content(render_poll_message(&poll));
+                    message
+                })
+        })
+        .await?;
+
+    confirm_vote(ctx, component, &voter, &poll, "vote again").await
+}
+
+async fn handle_approval_response(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    poll_id: &str,
+) -> Result<()> {
+    let indices: Vec<usize> = component
         .data
-        .options
+        .values
         .iter()
-        .filter_map(|o| match &o.resolved {
-            Some(v) => Some((o.name.clone(), v.clone())),
-            _ => None,
-        })
+        .filter_map(|v| v.parse().ok())
         .collect();
 
-    let poll_id = match options.get("id").expect("expected poll id") {
-        ApplicationCommandInteractionDataOptionValue::String(s) => s,
-        _ => panic!("poll id must be String"),
-    };
+    let voter = voter(ctx, poll_id, component.user.id).await;
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
 
-    let content = {
-        if let Some(owner) = {
-            let data_read = ctx.data.read().await;
-            let poll_map = data_read
-                .get::<PollData>()
-                .expect("Expected PollData in TypeMap.")
-                .clone();
-            poll_map.get(poll_id).map(|kv| kv.value().owner.clone())
-        } {
-            if user == &owner {
-                let data_read = ctx.data.read().await;
-                let poll_map = data_read
-                    .get::<PollData>()
-                    .expect("Expected PollData in TypeMap.")
-                    .clone();
-                poll_map.remove(poll_id);
-                "Poll deleted."
-            } else {
-                "Not an owner of this poll."
+        // Deselecting everything withdraws the voter rather than counting an empty ballot.
+        let mut entry = poll_map.get_mut(poll_id).ok_or_else(no_such_poll)?;
+        update_poll(&data_read, poll_id, &mut entry, |poll| {
+            if !poll.open {
+                return Err(Error::User("This poll is closed.".to_string()));
             }
-        } else {
-            "No poll with that ID."
-        }
+            let approved: BTreeSet<String> = indices
+                .iter()
+                .filter_map(|i| poll.options.get(*i).cloned())
+                .collect();
+            let ballot = if approved.is_empty() {
+                None
+            } else {
+                Some(Ballot::Approval { approved })
+            };
+            poll.cast(&voter, &component.user, component.member.as_ref(), ballot)
+                .map_err(Error::User)?;
+            poll.message
+                .get_or_insert((component.channel_id, component.message.id));
+            Ok(())
+        })?;
+        entry.clone()
+    };
+
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.content(render_poll_message(&poll));
+                    message
+                })
+        })
+        .await?;
+
+    confirm_vote(ctx, component, &voter, &poll, "select again").await
+}
+
+async fn reply_ephemeral(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    content: &str,
+) -> Result<()> {
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content);
+                    message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    message
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn handle_draft_start(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    poll_id: &str,
+) -> Result<()> {
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        poll_map.get(poll_id).map(|kv| kv.value().clone())
+    };
+
+    let poll = match poll {
+        Some(poll) if poll.open => poll,
+        Some(_) => return Err(Error::User("This poll is closed.".to_string())),
+        None => return Err(no_such_poll()),
+    };
+
+    let voter = voter(ctx, poll_id, component.user.id).await;
+    if let Some(refusal) = poll.voting_refusal(&voter, &component.user, component.member.as_ref()) {
+        return Err(Error::User(refusal));
+    }
+
+    let poll_id = poll_id.to_string();
+    {
+        let data_read = ctx.data.read().await;
+        let drafts = data_read
+            .get::<BallotDrafts>()
+            .expect("Expected BallotDrafts in TypeMap.")
+            .clone();
+        drafts.insert(
+            (poll_id.clone(), component.user.id),
+            BallotDraft {
+                picks: Vec::new(),
+                poll_message: (component.channel_id, component.message.id),
+            },
+        );
+    }
+
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    render_draft(message, &poll, &[]);
+                    message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    message
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn handle_draft_update(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    poll_id: &str,
+    reset: bool,
+) -> Result<()> {
+    let poll = {
+        let data_read = ctx.data.read().await;
+        let poll_map = data_read
+            .get::<PollData>()
+            .expect("Expected PollData in TypeMap.")
+            .clone();
+        poll_map.get(poll_id).map(|kv| kv.value().clone())
+    };
+
+    let picks = {
+        let data_read = ctx.data.read().await;
+        let drafts = data_read
+            .get::<BallotDrafts>()
+            .expect("Expected BallotDrafts in TypeMap.")
+            .clone();
+        let options = poll.as_ref().map_or(&[][..], |poll| &poll.options[..]);
+        let picks = drafts
+            .get_mut(&(poll_id.to_string(), component.user.id))
+            .map(|mut draft| {
+                if reset {
+                    draft.picks.clear();
+                } else {
+                    // Rankings never repeat an option, scores are given to options in turn.
+                    // Ranking menus pick options by index, score menus pick the score itself.
+                    let is_score = matches!(&poll, Some(poll) if poll.kind == PollKind::Score);
+                    for value in component.data.values.iter() {
+                        let pick = match is_score {
+                            true => Some(value),
+                            false => value.parse().ok().and_then(|i: usize| options.get(i)),
+                        };
+                        if let Some(pick) = pick {
+                            if draft.picks.len() < options.len()
+                                && (is_score || !draft.picks.contains(pick))
+                            {
+                                draft.picks.push(pick.clone());
+                            }
+                        }
+                    }
+                }
+                draft.picks.clone()
+            });
+        picks
     };
 
-    command
+    let (poll, picks) = match (poll, picks) {
+        (Some(poll), Some(picks)) => (poll, picks),
+        _ => return Err(Error::User(DRAFT_EXPIRED.to_string())),
+    };
+
+    component
         .create_interaction_response(&ctx.http, |response| {
             response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .kind(InteractionResponseType::UpdateMessage)
                 .interaction_response_data(|message| {
-                    message.content(content);
+                    render_draft(message, &poll, &picks);
                     message
                 })
         })
-        .await
-}
-
-async fn handle_default(ctx: &Context, command: &ApplicationCommandInteraction) -> Result<()> {
-    reply_to_command(ctx, command, &"Unimplmented command".to_string()).await
-}
-
-async fn handle_application_command(ctx: &Context, command: &ApplicationCommandInteraction) {
-    let command_name = command.data.name.as_str();
-    println!(
-        "Running command '{}' invoked by '{}'",
-        command_name,
-        command.user.tag()
-    );
-
-    increment_command(&ctx, command_name).await;
-
-    if let Err(why) = match command_name {
-        "poll-new" => handle_poll_new(&ctx, &command).await,
-        "poll-results" => handle_poll_results(&ctx, &command).await,
-        "poll-close" => handle_poll_close(&ctx, &command).await,
-        "poll-delete" => handle_poll_delete(&ctx, &command).await,
-        _ => handle_default(&ctx, &command).await,
-    } {
-        println!("Cannot respond to slash command {}: {}", command_name, why);
-    }
+        .await?;
+    Ok(())
 }
 
-async fn handle_poll_response(
+async fn handle_draft_submit(
     ctx: &Context,
     component: &MessageComponentInteraction,
+    poll_id: &str,
 ) -> Result<()> {
-    let response = &component.data.custom_id;
+    let draft = {
+        let data_read = ctx.data.read().await;
+        let drafts = data_read
+            .get::<BallotDrafts>()
+            .expect("Expected BallotDrafts in TypeMap.")
+            .clone();
+        drafts
+            .remove(&(poll_id.to_string(), component.user.id))
+            .map(|(_, draft)| draft)
+    };
 
-    let (poll_id, poll_option) = {
-        let mut splitter = response.splitn(2, ID_SEPARATOR);
-        (
-            splitter.next().unwrap().to_string(),
-            splitter.next().unwrap().to_string(),
-        )
+    let draft = match draft {
+        Some(draft) if !draft.picks.is_empty() => draft,
+        _ => return Err(Error::User(DRAFT_EXPIRED.to_string())),
     };
 
-    let poll_response_count = {
+    let voter = voter(ctx, poll_id, component.user.id).await;
+    let mut recorded = false;
+    let mut refusal = None;
+    let poll = {
         let data_read = ctx.data.read().await;
         let poll_map = data_read
             .get::<PollData>()
             .expect("Expected PollData in TypeMap.")
             .clone();
 
-        let count = match poll_map.entry(poll_id).and_modify(|poll| {
-            if poll.open {
-                poll.responses.insert(component.user.clone(), poll_option);
+        let poll = match poll_map.get_mut(poll_id) {
+            Some(mut entry) => {
+                update_poll(&data_read, poll_id, &mut entry, |poll| {
+                    let ballot = match poll.kind {
+                        PollKind::Score if draft.picks.len() == poll.options.len() => {
+                            let scores = poll
+                                .options
+                                .iter()
+                                .zip(draft.picks.iter())
+                                .filter_map(|(option, score)| {
+                                    Some((option.clone(), score.parse().ok()?))
+                                })
+                                .collect();
+                            Some(Ballot::Scores { scores })
+                        }
+                        PollKind::Ranked => Some(Ballot::Ranking(draft.picks.clone())),
+                        _ => None,
+                    };
+                    if let (true, Some(ballot)) = (poll.open, ballot) {
+                        match poll.cast(
+                            &voter,
+                            &component.user,
+                            component.member.as_ref(),
+                            Some(ballot),
+                        ) {
+                            Ok(()) => recorded = true,
+                            Err(e) => refusal = Some(e),
+                        }
+                    }
+                    Ok(())
+                })?;
+                Some(entry.clone())
             }
-        }) {
-            Occupied(e) => Some(e.get().responses.len()),
-            Vacant(_) => None,
+            None => None,
         };
-        count
+        poll
     };
 
-    let poll_prompt = {
-        let count_string = poll_response_count.map_or("?".to_string(), |x| x.to_string());
-
-        let mut prompt = component.message.content.clone();
-
-        if let Some(leader_ind) = prompt.rfind(COUNT_LEADER) {
-            prompt.truncate(leader_ind + COUNT_LEADER.len());
-        } else {
-            prompt.push_str(COUNT_LEADER);
-        }
-        prompt.push_str(count_string.as_str());
-        prompt
+    let content = match &poll {
+        Some(poll) if recorded => match poll.kind {
+            PollKind::Score => format!(
+                "Your scores have been recorded:{}",
+                format_scores(poll.options.iter().zip(draft.picks.iter()))
+            ),
+            _ => format!(
+                "Your ranking has been recorded:{}",
+                format_ranking(&draft.picks)
+            ),
+        },
+        Some(poll) if poll.open => refusal.unwrap_or_else(|| DRAFT_EXPIRED.to_string()),
+        Some(_) => "This poll is closed.".to_string(),
+        None => return Err(no_such_poll()),
     };
 
     component
-        .create_interaction_response(&ctx, |response| {
+        .create_interaction_response(&ctx.http, |response| {
             response
                 .kind(InteractionResponseType::UpdateMessage)
                 .interaction_response_data(|message| {
-                    message.content(poll_prompt);
+                    message.content(content);
+                    message.components(|components| components);
                     message
                 })
         })
-        .await
+        .await?;
+
+    if let (true, Some(poll)) = (recorded, poll) {
+        let (channel_id, message_id) = draft.poll_message;
+        channel_id
+            .edit_message(&ctx.http, message_id, |message| {
+                message.content(render_poll_message(&poll))
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn dispatch_component(ctx: &Context, component: &MessageComponentInteraction) -> Result<()> {
+    let id = match component.data.custom_id.parse::<ComponentId>() {
+        Ok(id) => id,
+        Err(e) => {
+            println!("Ignoring component interaction: {}", e);
+            return Err(Error::User("This control is out of date.".to_string()));
+        }
+    };
+    let poll_id = match poll_id_for_key(ctx, id.poll).await {
+        Some(poll_id) => poll_id,
+        None => return Err(no_such_poll()),
+    };
+    match id.control {
+        Control::Choose(index) => handle_poll_response(ctx, component, poll_id, Some(index)).await,
+        Control::ChoosePick => {
+            let index = component.data.values.first().and_then(|v| v.parse().ok());
+            handle_poll_response(ctx, component, poll_id, index).await
+        }
+        Control::ApprovePick => handle_approval_response(ctx, component, &poll_id).await,
+        Control::DraftStart => handle_draft_start(ctx, component, &poll_id).await,
+        Control::DraftPick => handle_draft_update(ctx, component, &poll_id, false).await,
+        Control::DraftReset => handle_draft_update(ctx, component, &poll_id, true).await,
+        Control::DraftSubmit => handle_draft_submit(ctx, component, &poll_id).await,
+        Control::Withdraw => handle_vote_control(ctx, component, &poll_id, false).await,
+        Control::Abstain => handle_vote_control(ctx, component, &poll_id, true).await,
+    }
 }
 
 async fn handle_message_component(ctx: &Context, component: &MessageComponentInteraction) {
-    if let Err(why) = handle_poll_response(&ctx, &component).await {
-        println!("Failed to handle component interaction: {}", why);
+    if let Err(error) = dispatch_component(ctx, component).await {
+        respond_to_component_error(ctx, component, &error).await;
     }
 }
 
-struct Handler;
+fn create_poll_admin_subcommand<'a>(
+    option: &'a mut CreateApplicationCommandOption,
+    name: &str,
+    description: &str,
+) -> &'a mut CreateApplicationCommandOption {
+    option
+        .name(name)
+        .description(description)
+        .kind(ApplicationCommandOptionType::SubCommand)
+        .create_sub_option(|option| {
+            option
+                .name("id")
+                .description("Unique ID string for poll")
+                .kind(ApplicationCommandOptionType::String)
+                .required(true)
+        })
+        .create_sub_option(|option| {
+            option
+                .name("user")
+                .description("User to add or remove as a co-owner")
+                .kind(ApplicationCommandOptionType::User)
+                .required(false)
+        })
+        .create_sub_option(|option| {
+            option
+                .name("role")
+                .description("Role whose members can administer the poll")
+                .kind(ApplicationCommandOptionType::Role)
+                .required(false)
+        })
+}
+
+struct Handler {
+    scheduler_started: AtomicBool,
+}
 
 #[async_trait]
 impl EventHandler for Handler {
@@ -476,6 +3698,11 @@ impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
 
+        // Ready fires again on reconnect, but only one scheduler should ever run.
+        if !self.scheduler_started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(run_scheduler(ctx.clone()));
+        }
+
         let guild_id = GuildId(
             env::var("GUILD_ID")
                 .expect("Expected GUILD_ID in environment")
@@ -507,17 +3734,157 @@ impl EventHandler for Handler {
                             option
                                 .name("options")
                                 .description(format!(
-                                    "List of options separated by {0} e.g: A{0}B{0}C{0}D (max 5)",
-                                    OPTION_SEPARATOR
+                                    "List of options separated by {0} e.g: A{0}B{0}C{0}D (max {1})",
+                                    OPTION_SEPARATOR, MAX_OPTIONS
                                 ))
                                 .kind(ApplicationCommandOptionType::String)
                                 .required(true)
                         })
+                        .create_option(|option| {
+                            option
+                                .name("kind")
+                                .description("How voters respond (default plurality)")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                                .add_string_choice("Plurality: pick one option", "plurality")
+                                .add_string_choice("Ranked choice: order the options", "ranked")
+                                .add_string_choice("Approval: pick any number of options", "approval")
+                                .add_string_choice("Score: rate every option", "score")
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("method")
+                                .description("How ranked ballots are counted (default instant runoff)")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                                .add_string_choice("Instant runoff", "instant-runoff")
+                                .add_string_choice("Schulze", "schulze")
+                                .add_string_choice("Ranked pairs", "ranked-pairs")
+                                .add_string_choice("Single transferable vote", "stv")
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("seats")
+                                .description("Number of winners in a ranked poll, counted by STV (default 1)")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("eligible-role")
+                                .description("Only members with this role may vote")
+                                .kind(ApplicationCommandOptionType::Role)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("eligible-users")
+                                .description("Only these users may vote (mentions), alongside any eligible role")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("quorum")
+                                .description("Minimum turnout: a number of responses, or a percentage of eligible voters like 50%")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("threshold")
+                                .description("Share of votes the leading option needs to pass")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                                .add_string_choice("Simple majority", "simple-majority")
+                                .add_string_choice("Two-thirds majority", "two-thirds")
+                                .add_string_choice("Unanimity", "unanimity")
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("verifiable")
+                                .description("Commit votes to a public board, send receipts and publish a checkable tally")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("trustees")
+                                .description("Encrypt votes so only these users (mentions) can jointly decrypt the results")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("trustee-threshold")
+                                .description("How many trustees must submit key shares to decrypt (default all)")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("sealed")
+                                .description("Hide the results from everyone until you reveal them with /poll-reveal")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("allow-abstain")
+                                .description("Offer an Abstain button that counts towards turnout only")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("vote-changes")
+                                .description("Whether voters can change their vote (default until close)")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                                .add_string_choice("Changeable until the poll closes", "until-close")
+                                .add_string_choice("Locked after the first vote", "locked")
+                                .add_string_choice("Changeable for change-window minutes", "timed")
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("change-window")
+                                .description("Minutes a voter can change their vote for, with timed vote changes")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("closes-in")
+                                .description("Close the poll and send you the results after this many minutes")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("closes-at")
+                                .description("Close the poll and send you the results at this time, e.g. 2024-01-31 18:00 (UTC)")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("min-score")
+                                .description("Lowest score a voter can give in a score poll (default 0)")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("max-score")
+                                .description("Highest score a voter can give in a score poll (default 5)")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(false)
+                        })
                 })
                 .create_application_command(|command| {
                     command
                         .name("poll-results")
-                        .description("Retrieve poll results (poll owner only)")
+                        .description("Retrieve poll results (poll administrators only)")
                         .create_option(|option| {
                             option
                                 .name("id")
@@ -529,7 +3896,113 @@ impl EventHandler for Handler {
                 .create_application_command(|command| {
                     command
                         .name("poll-close")
-                        .description("Stop accepting responses (poll owner only)")
+                        .description("Stop accepting responses (poll administrators only)")
+                        .create_option(|option| {
+                            option
+                                .name("id")
+                                .description("Unique ID string for poll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-reveal")
+                        .description("Close a sealed poll and publish its results in the channel (poll administrators only)")
+                        .create_option(|option| {
+                            option
+                                .name("id")
+                                .description("Unique ID string for poll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-publish")
+                        .description("Post the results in the poll's channel (poll administrators only)")
+                        .create_option(|option| {
+                            option
+                                .name("id")
+                                .description("Unique ID string for poll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("reply")
+                                .description("Post the results as a reply to the poll message")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(false)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("force")
+                                .description("Publish even though the poll is still open")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(false)
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-export")
+                        .description("DM yourself the results as a file (poll administrators only)")
+                        .create_option(|option| {
+                            option
+                                .name("id")
+                                .description("Unique ID string for poll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("format")
+                                .description("File format (default CSV)")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                                .add_string_choice("CSV", "csv")
+                                .add_string_choice("JSON", "json")
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-admin")
+                        .description("Manage who can administer a poll (poll administrators only)")
+                        .create_option(|option| {
+                            create_poll_admin_subcommand(option, "add", "Let a user or role see results, close and delete the poll")
+                        })
+                        .create_option(|option| {
+                            create_poll_admin_subcommand(option, "remove", "Stop a user or role from administering the poll")
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-my-vote")
+                        .description("Privately show your own response to a poll")
+                        .create_option(|option| {
+                            option
+                                .name("id")
+                                .description("Unique ID string for poll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-board")
+                        .description("Privately fetch the bulletin board of vote commitments for a verifiable poll")
+                        .create_option(|option| {
+                            option
+                                .name("id")
+                                .description("Unique ID string for poll")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("poll-trustee-share")
+                        .description("Submit your key share to decrypt a closed encrypted poll")
                         .create_option(|option| {
                             option
                                 .name("id")
@@ -537,11 +4010,18 @@ impl EventHandler for Handler {
                                 .kind(ApplicationCommandOptionType::String)
                                 .required(true)
                         })
+                        .create_option(|option| {
+                            option
+                                .name("share")
+                                .description("The key share you were sent when the poll was created")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
                 })
                 .create_application_command(|command| {
                     command
                         .name("poll-delete")
-                        .description("Irrevocably delete poll (poll owner only)")
+                        .description("Irrevocably delete poll (poll administrators only)")
                         .create_option(|option| {
                             option
                                 .name("id")
@@ -560,8 +4040,44 @@ impl EventHandler for Handler {
     }
 }
 
+/// Offline check of a published proof, and optionally that a receipt's commitment counted.
+fn run_verifier(args: &[String]) -> std::result::Result<String, String> {
+    let path = args
+        .first()
+        .ok_or("usage: secret-ballot-bot verify <proof file> [commitment]")?;
+    let json = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let proof: Proof =
+        serde_json::from_str(&json).map_err(|e| format!("{} is not a proof: {}", path, e))?;
+    verify::verify(&proof)?;
+
+    let mut summary = format!("Proof for poll {} is valid.", proof.board.poll_id);
+    for (option, count) in &proof.tally {
+        summary.push_str(&format!("\n{}\t{}", count, option));
+    }
+    summary.push_str(&format!("\n{}\tabstained", proof.abstentions));
+    if let Some(commitment) = args.get(1) {
+        match proof.openings.iter().find(|o| &o.commitment == commitment) {
+            Some(_) => summary.push_str(&format!("\nCommitment {} was counted.", commitment)),
+            None => return Err(format!("commitment {} was not counted", commitment)),
+        }
+    }
+    Ok(summary)
+}
+
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("verify") {
+        match run_verifier(&args[2..]) {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => {
+                println!("Verification failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     dotenv().ok();
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
@@ -574,16 +4090,35 @@ async fn main() {
 
     // Build our client.
     let mut client = Client::builder(token)
-        .event_handler(Handler)
+        .event_handler(Handler {
+            scheduler_started: AtomicBool::new(false),
+        })
         .application_id(application_id)
         .await
         .expect("Error creating client");
 
+    // Polls are kept in a SQLite file so that they survive restarts.
+    let database_path = env::var("DATABASE_PATH").unwrap_or_else(|_| "polls.db".to_string());
+    let store = SqliteStore::open(&database_path).expect("Error opening poll database");
+    let voter_key = VoterKey::load().expect("Error loading ballot key");
+    let polls: DashMap<String, Poll> = store
+        .load_polls()
+        .expect("Error loading polls from database")
+        .into_iter()
+        .collect();
+    let poll_keys: DashMap<u64, String> =
+        polls.iter().map(|kv| (kv.key, kv.key().clone())).collect();
+    println!("Loaded {} polls from {}", polls.len(), database_path);
+
     {
         let mut data = client.data.write().await;
 
         data.insert::<CommandCounter>(Arc::new(DashMap::default()));
-        data.insert::<PollData>(Arc::new(DashMap::default()));
+        data.insert::<PollData>(Arc::new(polls));
+        data.insert::<PollKeys>(Arc::new(poll_keys));
+        data.insert::<PollStorage>(Arc::new(store));
+        data.insert::<BallotKey>(Arc::new(voter_key));
+        data.insert::<BallotDrafts>(Arc::new(DashMap::default()));
     }
 
     // Finally, start a single shard, and start listening to events.
@@ -593,3 +4128,181 @@ async fn main() {
         println!("Client error: {:?}", why);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_ballots_decrypt_to_their_tally() {
+        let (public_key, shares) = elgamal::deal(1, 1);
+        let mut poll = Poll {
+            options: vec!["Yes".to_string(), "No".to_string()],
+            open: true,
+            election: Some(Election {
+                public_key,
+                threshold: 1,
+                trustees: vec![(UserId(9), shares[0].verification_key())],
+                partials: BTreeMap::new(),
+                tally: None,
+            }),
+            ..Default::default()
+        };
+
+        let key = VoterKey::new([3; 32]);
+        let user = User::default();
+        for (id, ballot) in [(1, "Yes"), (2, "No"), (3, "Yes")] {
+            let ballot = Some(Ballot::Choice(ballot.to_string()));
+            poll.cast(&key.voter("motion", UserId(id)), &user, None, ballot)
+                .unwrap();
+        }
+        poll.cast(
+            &key.voter("motion", UserId(4)),
+            &user,
+            None,
+            Some(Ballot::Abstain),
+        )
+        .unwrap();
+        assert!(poll
+            .ballot_box
+            .ballots()
+            .all(|b| matches!(b, Ballot::Encrypted { .. })));
+
+        let tally: Vec<Option<u64>> = poll
+            .encrypted_sums()
+            .iter()
+            .map(|sum| {
+                let partials = vec![(shares[0].index, shares[0].partial_decrypt(sum))];
+                elgamal::combine(&partials, sum, poll.ballot_box.len() as u64)
+            })
+            .collect();
+        assert_eq!(tally, vec![Some(2), Some(1)]);
+    }
+
+    #[test]
+    fn only_standing_ballots_are_held_to_the_change_policy() {
+        let mut poll = Poll {
+            options: vec!["Yes".to_string(), "No".to_string()],
+            open: true,
+            ..Default::default()
+        };
+        let key = VoterKey::new([3; 32]);
+        let voter = key.voter("motion", UserId(1));
+        let user = User::default();
+        let yes = || Some(Ballot::Choice("Yes".to_string()));
+        poll.cast(&voter, &user, None, yes()).unwrap();
+        poll.cast(&voter, &user, None, None).unwrap();
+
+        poll.change_policy = ChangePolicy::Locked;
+        poll.cast(&voter, &user, None, yes()).unwrap();
+        assert!(poll.cast(&voter, &user, None, None).is_err());
+        assert!(poll.ballot_box.get(&voter).is_some());
+    }
+
+    #[test]
+    fn user_lists_take_mentions_and_ids_only() {
+        let users = parse_user_ids("<@1>, <@!2>  3").unwrap();
+        assert_eq!(
+            users,
+            [UserId(1), UserId(2), UserId(3)].into_iter().collect()
+        );
+        assert!(parse_user_ids("").unwrap().is_empty());
+        for bad in ["<@&4>", "<#5>", "alice", "+6", "0", "<@7"] {
+            assert!(parse_user_ids(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_and_never_formulas() {
+        assert_eq!(csv_field("Pizza"), "Pizza");
+        assert_eq!(csv_field("Pizza, large"), "\"Pizza, large\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        for formula in ["=1+1", "+A1", "-A1+1", "@SUM(A1)", "\t=1"] {
+            assert_eq!(csv_field(formula), format!("'{}", formula));
+        }
+        assert_eq!(csv_field("\r=1"), "\"'\r=1\"");
+        for number in ["-2", "-1.50", "+1", "3"] {
+            assert_eq!(csv_field(number), number);
+        }
+        assert_eq!(
+            csv_field("=HYPERLINK(\"x\",\"y\")"),
+            "\"'=HYPERLINK(\"\"x\"\",\"\"y\"\")\""
+        );
+        assert_eq!(
+            csv_row(&["prompt", "Lunch, today?", "=cmd"]),
+            "prompt,\"Lunch, today?\",'=cmd\n"
+        );
+    }
+
+    #[test]
+    fn share_quorums_of_unknown_or_empty_electorates_are_not_met() {
+        let mut poll = Poll {
+            options: vec!["Yes".to_string(), "No".to_string()],
+            open: true,
+            quorum: Some(Quorum::Share(50.0)),
+            threshold: Some(Threshold::SimpleMajority),
+            ..Default::default()
+        };
+        let key = VoterKey::new([3; 32]);
+        let ballot = Some(Ballot::Choice("Yes".to_string()));
+        poll.cast(
+            &key.voter("motion", UserId(1)),
+            &User::default(),
+            None,
+            ballot,
+        )
+        .unwrap();
+
+        assert_eq!(
+            governance_report(&poll, None),
+            "\nQuorum unknown: could not count the eligible voters\nNo option passed: quorum not met"
+        );
+        assert_eq!(Quorum::Share(50.0).required(Some(0)), Some(1));
+        assert!(governance_report(&poll, Some(0)).starts_with("\nQuorum met: 1 of 1"));
+        poll.ballot_box = Default::default();
+        assert!(governance_report(&poll, Some(0)).contains("Quorum not met: 0 of 1"));
+    }
+
+    #[test]
+    fn motions_name_the_passing_option_and_fail_without_votes() {
+        let poll = Poll {
+            options: vec!["Yes".to_string()],
+            threshold: Some(Threshold::TwoThirds),
+            ..Default::default()
+        };
+        assert_eq!(
+            governance_report(&poll, None),
+            "\nNo option passed: no votes were cast"
+        );
+
+        let mut poll = Poll {
+            options: vec!["Yes".to_string(), "No".to_string()],
+            open: true,
+            threshold: Some(Threshold::SimpleMajority),
+            ..Default::default()
+        };
+        let key = VoterKey::new([3; 32]);
+        for (id, ballot) in [(1, "No"), (2, "No"), (3, "Yes")] {
+            let ballot = Some(Ballot::Choice(ballot.to_string()));
+            poll.cast(
+                &key.voter("motion", UserId(id)),
+                &User::default(),
+                None,
+                ballot,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            governance_report(&poll, None),
+            "\nNo passed: No has 2 of 3 votes (66.7%), needing more than half"
+        );
+        for threshold in [
+            Threshold::SimpleMajority,
+            Threshold::TwoThirds,
+            Threshold::Unanimity,
+        ] {
+            assert!(!threshold.is_met(0, 0));
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::{fmt, sync::Mutex};
+
+use rusqlite::{params, Connection};
+
+use crate::Poll;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    Serde(serde_json::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            StorageError::Serde(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Serde(e)
+    }
+}
+
+pub type StorageResult<T> = std::result::Result<T, StorageError>;
+
+/// Durable backing store for polls, keyed by poll id.
+///
+/// The in-memory `PollData` map is the working copy; a `PollStore` is read once at startup and
+/// written through on every change so that a restart is invisible to voters.
+pub trait PollStore: Send + Sync {
+    fn load_polls(&self) -> StorageResult<Vec<(String, Poll)>>;
+    fn save_poll(&self, id: &str, poll: &Poll) -> StorageResult<()>;
+    fn delete_poll(&self, id: &str) -> StorageResult<()>;
+    /// A poll key that has never been handed out before, counting up from 1.
+    fn next_poll_key(&self) -> StorageResult<u64>;
+}
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> StorageResult<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS polls (id TEXT PRIMARY KEY, poll TEXT NOT NULL)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS meta (name TEXT PRIMARY KEY, value INTEGER NOT NULL)",
+            [],
+        )?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl PollStore for SqliteStore {
+    fn load_polls(&self) -> StorageResult<Vec<(String, Poll)>> {
+        let conn = self.conn.lock().expect("sqlite connection poisoned");
+        let mut stmt = conn.prepare("SELECT id, poll FROM polls")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut polls = Vec::new();
+        for row in rows {
+            let (id, json) = row?;
+            polls.push((id, serde_json::from_str(&json)?));
+        }
+        Ok(polls)
+    }
+
+    fn save_poll(&self, id: &str, poll: &Poll) -> StorageResult<()> {
+        let json = serde_json::to_string(poll)?;
+        let conn = self.conn.lock().expect("sqlite connection poisoned");
+        conn.execute(
+            "INSERT INTO polls (id, poll) VALUES (?1, ?2) ON CONFLICT(id) DO UPDATE SET poll = excluded.poll",
+            params![id, json],
+        )?;
+        Ok(())
+    }
+
+    fn delete_poll(&self, id: &str) -> StorageResult<()> {
+        let conn = self.conn.lock().expect("sqlite connection poisoned");
+        conn.execute("DELETE FROM polls WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn next_poll_key(&self) -> StorageResult<u64> {
+        let conn = self.conn.lock().expect("sqlite connection poisoned");
+        let key: i64 = conn.query_row(
+            "INSERT INTO meta (name, value) VALUES ('last_poll_key', 1) \
+             ON CONFLICT(name) DO UPDATE SET value = value + 1 RETURNING value",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(key as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_keys_count_up_and_are_never_reused() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let poll = Poll::default();
+        store.save_poll("lunch", &poll).unwrap();
+        assert_eq!(store.next_poll_key().unwrap(), 1);
+        store.delete_poll("lunch").unwrap();
+        assert_eq!(store.next_poll_key().unwrap(), 2);
+    }
+}
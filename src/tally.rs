@@ -0,0 +1,463 @@
+//! Vote counting for ranked ballots, independent of how the ballots were collected.
+//!
+//! A ballot is a list of options in order of preference. Options left off a ballot are ranked
+//! below every option on it and level with each other.
+
+pub struct RunoffRound {
+    pub counts: Vec<(String, usize)>,
+    pub exhausted: usize,
+    pub eliminated: Option<String>,
+}
+
+/// Count ranked ballots by instant runoff, returning every round and the winner (if any).
+///
+/// Each round a ballot counts for its highest-ranked option still in the running. An option
+/// with a strict majority of the non-exhausted ballots wins; otherwise the option with the
+/// fewest votes is eliminated, with ties broken against the option listed last.
+pub fn instant_runoff(
+    options: &[String],
+    ballots: &[&[String]],
+) -> (Vec<RunoffRound>, Option<String>) {
+    let mut continuing: Vec<String> = options.to_vec();
+    let mut rounds = Vec::new();
+
+    loop {
+        let mut counts: Vec<(String, usize)> = continuing.iter().map(|o| (o.clone(), 0)).collect();
+        let mut exhausted = 0;
+        for ballot in ballots.iter() {
+            match ballot.iter().find(|o| continuing.contains(o)) {
+                Some(choice) => {
+                    if let Some(count) = counts.iter_mut().find(|(o, _)| o == choice) {
+                        count.1 += 1;
+                    }
+                }
+                None => exhausted += 1,
+            }
+        }
+        let active: usize = counts.iter().map(|(_, c)| c).sum();
+
+        let leader = counts.iter().max_by_key(|(_, c)| *c).cloned();
+        let winner = match leader {
+            Some((option, count)) if count * 2 > active || continuing.len() == 1 => Some(option),
+            _ => None,
+        };
+        if winner.is_some() || continuing.is_empty() || active == 0 {
+            rounds.push(RunoffRound {
+                counts,
+                exhausted,
+                eliminated: None,
+            });
+            return (rounds, winner);
+        }
+
+        let fewest = counts.iter().map(|(_, c)| *c).min().unwrap_or(0);
+        let eliminated = counts
+            .iter()
+            .rev()
+            .find(|(_, c)| *c == fewest)
+            .map(|(o, _)| o.clone());
+        continuing.retain(|o| Some(o) != eliminated.as_ref());
+        rounds.push(RunoffRound {
+            counts,
+            exhausted,
+            eliminated,
+        });
+    }
+}
+
+pub struct StvRound {
+    pub votes: Vec<(String, f64)>,
+    pub exhausted: f64,
+    /// Options reaching the quota this round, with the fraction of each ballot passed on.
+    pub elected: Vec<(String, f64)>,
+    pub excluded: Option<String>,
+}
+
+pub struct Stv {
+    pub quota: usize,
+    pub rounds: Vec<StvRound>,
+    pub elected: Vec<String>,
+}
+
+/// Fill `seats` seats from ranked ballots by single transferable vote.
+///
+/// Uses the Droop quota and weighted inclusive Gregory transfers: when an option is elected every
+/// ballot counting for it carries on at a weight reduced by the share of its votes above the
+/// quota. If nobody reaches the quota the option with the fewest votes is excluded, with ties
+/// broken against the option listed last, and its ballots carry on at their current weight.
+pub fn single_transferable_vote(options: &[String], ballots: &[&[String]], seats: usize) -> Stv {
+    let quota = ballots.len() / (seats + 1) + 1;
+    let mut weights: Vec<f64> = vec![1.0; ballots.len()];
+    let mut continuing: Vec<String> = options.to_vec();
+    let mut elected: Vec<String> = Vec::new();
+    let mut rounds = Vec::new();
+
+    while elected.len() < seats && !continuing.is_empty() {
+        let mut votes: Vec<(String, f64)> = continuing.iter().map(|o| (o.clone(), 0.0)).collect();
+        let mut exhausted = 0.0;
+        let choices: Vec<Option<usize>> = ballots
+            .iter()
+            .map(|ballot| {
+                ballot
+                    .iter()
+                    .find_map(|o| continuing.iter().position(|c| c == o))
+            })
+            .collect();
+        for (choice, weight) in choices.iter().zip(weights.iter()) {
+            match choice {
+                Some(i) => votes[*i].1 += weight,
+                None => exhausted += weight,
+            }
+        }
+
+        // Once the remaining options can only just fill the remaining seats they are all elected.
+        if elected.len() + continuing.len() <= seats {
+            elected.append(&mut continuing);
+            let newly: Vec<(String, f64)> = votes.iter().map(|(o, _)| (o.clone(), 0.0)).collect();
+            rounds.push(StvRound {
+                votes,
+                exhausted,
+                elected: newly,
+                excluded: None,
+            });
+            break;
+        }
+
+        let mut winners: Vec<usize> = (0..votes.len())
+            .filter(|&i| votes[i].1 >= quota as f64)
+            .collect();
+        winners.sort_by(|&a, &b| votes[b].1.total_cmp(&votes[a].1));
+        winners.truncate(seats - elected.len());
+
+        if !winners.is_empty() {
+            let mut newly = Vec::new();
+            for &i in winners.iter() {
+                let total = votes[i].1;
+                let transfer = (total - quota as f64) / total;
+                for (choice, weight) in choices.iter().zip(weights.iter_mut()) {
+                    if *choice == Some(i) {
+                        *weight *= transfer;
+                    }
+                }
+                newly.push((votes[i].0.clone(), transfer));
+            }
+            for (option, _) in newly.iter() {
+                elected.push(option.clone());
+            }
+            continuing.retain(|o| !newly.iter().any(|(n, _)| n == o));
+            rounds.push(StvRound {
+                votes,
+                exhausted,
+                elected: newly,
+                excluded: None,
+            });
+        } else {
+            let fewest = votes.iter().map(|(_, v)| *v).fold(f64::INFINITY, f64::min);
+            let excluded = votes
+                .iter()
+                .rev()
+                .find(|(_, v)| *v == fewest)
+                .map(|(o, _)| o.clone());
+            continuing.retain(|o| Some(o) != excluded.as_ref());
+            rounds.push(StvRound {
+                votes,
+                exhausted,
+                elected: Vec::new(),
+                excluded,
+            });
+        }
+    }
+
+    Stv {
+        quota,
+        rounds,
+        elected,
+    }
+}
+
+/// Number of ballots preferring each option over each other option, indexed like the options.
+pub fn pairwise_preferences(options: &[String], ballots: &[&[String]]) -> Vec<Vec<usize>> {
+    let n = options.len();
+    let mut d = vec![vec![0; n]; n];
+    for ballot in ballots.iter() {
+        let rank: Vec<Option<usize>> = options
+            .iter()
+            .map(|o| ballot.iter().position(|b| b == o))
+            .collect();
+        for i in 0..n {
+            for j in 0..n {
+                let prefers = match (rank[i], rank[j]) {
+                    (Some(ri), Some(rj)) => ri < rj,
+                    (Some(_), None) => true,
+                    _ => false,
+                };
+                if prefers {
+                    d[i][j] += 1;
+                }
+            }
+        }
+    }
+    d
+}
+
+/// The smallest non-empty set of options that each beat every option outside the set.
+pub fn smith_set(d: &[Vec<usize>]) -> Vec<usize> {
+    let n = d.len();
+    // reach[i][j]: i beats or ties j, directly or through a chain of other options.
+    let mut reach: Vec<Vec<bool>> = (0..n)
+        .map(|i| (0..n).map(|j| i == j || d[i][j] >= d[j][i]).collect())
+        .collect();
+    for k in 0..n {
+        for i in 0..n {
+            for j in 0..n {
+                if reach[i][k] && reach[k][j] {
+                    reach[i][j] = true;
+                }
+            }
+        }
+    }
+    (0..n).filter(|&i| reach[i].iter().all(|&r| r)).collect()
+}
+
+pub struct Schulze {
+    pub preferences: Vec<Vec<usize>>,
+    pub strongest_paths: Vec<Vec<usize>>,
+    pub smith_set: Vec<usize>,
+    /// Every option whose strongest path to each other option is at least as strong as the
+    /// reverse; more than one means the count is tied.
+    pub winners: Vec<usize>,
+}
+
+/// Count ranked ballots by the Schulze method, measuring path strength by winning votes.
+pub fn schulze(options: &[String], ballots: &[&[String]]) -> Schulze {
+    let n = options.len();
+    let d = pairwise_preferences(options, ballots);
+
+    let mut p = vec![vec![0; n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i != j && d[i][j] > d[j][i] {
+                p[i][j] = d[i][j];
+            }
+        }
+    }
+    for k in 0..n {
+        for i in 0..n {
+            for j in 0..n {
+                if i != j && i != k && j != k {
+                    p[i][j] = p[i][j].max(p[i][k].min(p[k][j]));
+                }
+            }
+        }
+    }
+
+    let winners = (0..n)
+        .filter(|&i| (0..n).all(|j| i == j || p[i][j] >= p[j][i]))
+        .collect();
+
+    Schulze {
+        smith_set: smith_set(&d),
+        preferences: d,
+        strongest_paths: p,
+        winners,
+    }
+}
+
+pub struct RankedPairs {
+    pub preferences: Vec<Vec<usize>>,
+    pub smith_set: Vec<usize>,
+    /// Every majority `(winner, loser)` in the order considered, and whether it was locked in.
+    pub pairs: Vec<(usize, usize, bool)>,
+    pub winner: Option<usize>,
+}
+
+/// Count ranked ballots by Tideman's ranked pairs.
+///
+/// Majorities are considered from largest to smallest winning vote (then smallest losing vote,
+/// then option order) and locked in unless they would complete a cycle.
+pub fn ranked_pairs(options: &[String], ballots: &[&[String]]) -> RankedPairs {
+    let n = options.len();
+    let d = pairwise_preferences(options, ballots);
+
+    let mut majorities: Vec<(usize, usize)> = Vec::new();
+    for (i, row) in d.iter().enumerate() {
+        for (j, &count) in row.iter().enumerate() {
+            if count > d[j][i] {
+                majorities.push((i, j));
+            }
+        }
+    }
+    majorities.sort_by(|&(a, b), &(c, e)| d[c][e].cmp(&d[a][b]).then(d[b][a].cmp(&d[e][c])));
+
+    let mut locked = vec![vec![false; n]; n];
+    let mut pairs = Vec::new();
+    for (i, j) in majorities {
+        let cycle = reaches(&locked, j, i);
+        if !cycle {
+            locked[i][j] = true;
+        }
+        pairs.push((i, j, !cycle));
+    }
+
+    let sources: Vec<usize> = (0..n).filter(|&j| (0..n).all(|i| !locked[i][j])).collect();
+    let winner = match sources.as_slice() {
+        [winner] => Some(*winner),
+        _ => None,
+    };
+
+    RankedPairs {
+        smith_set: smith_set(&d),
+        preferences: d,
+        pairs,
+        winner,
+    }
+}
+
+fn reaches(edges: &[Vec<bool>], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; edges.len()];
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        if i == to {
+            return true;
+        }
+        if !seen[i] {
+            seen[i] = true;
+            stack.extend((0..edges.len()).filter(|&j| edges[i][j]));
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(names: &str) -> Vec<String> {
+        names.chars().map(|c| c.to_string()).collect()
+    }
+
+    /// Expand `(count, "ABC")` pairs into that many copies of each ranking.
+    fn ballots(groups: &[(usize, &str)]) -> Vec<Vec<String>> {
+        groups
+            .iter()
+            .flat_map(|&(count, ranking)| std::iter::repeat_n(options(ranking), count))
+            .collect()
+    }
+
+    fn refs(ballots: &[Vec<String>]) -> Vec<&[String]> {
+        ballots.iter().map(|b| b.as_slice()).collect()
+    }
+
+    #[test]
+    fn instant_runoff_transfers_eliminated_votes() {
+        let options = options("ABC");
+        let ballots = ballots(&[(4, "AB"), (3, "BA"), (2, "CB")]);
+        let (rounds, winner) = instant_runoff(&options, &refs(&ballots));
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].eliminated.as_deref(), Some("C"));
+        assert_eq!(
+            rounds[1].counts,
+            vec![("A".to_string(), 4), ("B".to_string(), 5)]
+        );
+        assert_eq!(winner.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn instant_runoff_counts_exhausted_ballots() {
+        let options = options("ABC");
+        let ballots = ballots(&[(3, "A"), (2, "B"), (2, "C")]);
+        let (rounds, winner) = instant_runoff(&options, &refs(&ballots));
+        assert_eq!(rounds[0].eliminated.as_deref(), Some("C"));
+        assert_eq!(rounds[1].exhausted, 2);
+        assert_eq!(winner.as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn pairwise_ranks_listed_options_above_unlisted() {
+        let options = options("ABC");
+        let ballots = ballots(&[(1, "B")]);
+        let d = pairwise_preferences(&options, &refs(&ballots));
+        assert_eq!(d, vec![vec![0, 0, 0], vec![1, 0, 1], vec![0, 0, 0]]);
+    }
+
+    // The example from Schulze's paper, with five options and 45 voters.
+    fn schulze_example() -> (Vec<String>, Vec<Vec<String>>) {
+        let options = options("ABCDE");
+        let ballots = ballots(&[
+            (5, "ACBED"),
+            (5, "ADECB"),
+            (8, "BEDAC"),
+            (3, "CABED"),
+            (7, "CAEBD"),
+            (2, "CBADE"),
+            (7, "DCEBA"),
+            (8, "EBADC"),
+        ]);
+        (options, ballots)
+    }
+
+    #[test]
+    fn schulze_matches_reference_example() {
+        let (options, ballots) = schulze_example();
+        let result = schulze(&options, &refs(&ballots));
+        assert_eq!(result.preferences[0], vec![0, 20, 26, 30, 22]);
+        assert_eq!(result.strongest_paths[0], vec![0, 28, 28, 30, 24]);
+        assert_eq!(result.strongest_paths[4], vec![25, 28, 28, 31, 0]);
+        assert_eq!(result.winners, vec![4]);
+        assert_eq!(result.smith_set.len(), 5);
+    }
+
+    #[test]
+    fn condorcet_winner_wins_everything() {
+        let options = options("ABC");
+        let ballots = ballots(&[(3, "ABC"), (2, "BCA"), (2, "CBA")]);
+        let refs = refs(&ballots);
+        assert_eq!(smith_set(&pairwise_preferences(&options, &refs)), vec![1]);
+        assert_eq!(schulze(&options, &refs).winners, vec![1]);
+        assert_eq!(ranked_pairs(&options, &refs).winner, Some(1));
+    }
+
+    #[test]
+    fn ranked_pairs_skips_pair_completing_a_cycle() {
+        let options = options("ABC");
+        let ballots = ballots(&[(4, "ABC"), (3, "BCA"), (2, "CAB")]);
+        let result = ranked_pairs(&options, &refs(&ballots));
+        // B>C 7-2 and A>B 6-3 are locked, C>A 5-4 would close the cycle.
+        assert_eq!(
+            result.pairs,
+            vec![(1, 2, true), (0, 1, true), (2, 0, false)]
+        );
+        assert_eq!(result.winner, Some(0));
+        assert_eq!(result.smith_set, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn tied_schulze_reports_every_winner() {
+        let options = options("AB");
+        let ballots = ballots(&[(1, "AB"), (1, "BA")]);
+        assert_eq!(schulze(&options, &refs(&ballots)).winners, vec![0, 1]);
+    }
+
+    #[test]
+    fn stv_transfers_surplus_then_excludes() {
+        let options = options("ABCD");
+        let ballots = ballots(&[(6, "AB"), (3, "CB"), (1, "DC")]);
+        let result = single_transferable_vote(&options, &refs(&ballots), 2);
+        assert_eq!(result.quota, 4);
+        assert_eq!(result.rounds.len(), 3);
+        assert_eq!(result.rounds[0].elected[0].0, "A");
+        assert!((result.rounds[0].elected[0].1 - 1.0 / 3.0).abs() < 1e-9);
+        assert!((result.rounds[1].votes[0].1 - 2.0).abs() < 1e-9);
+        assert_eq!(result.rounds[1].excluded.as_deref(), Some("D"));
+        assert_eq!(result.elected, vec!["A".to_string(), "C".to_string()]);
+    }
+
+    #[test]
+    fn stv_fills_remaining_seats_when_options_run_out() {
+        let options = options("ABC");
+        let ballots = ballots(&[(2, "A"), (1, "B")]);
+        let result = single_transferable_vote(&options, &refs(&ballots), 2);
+        assert_eq!(result.elected, vec!["A".to_string(), "B".to_string()]);
+        assert_eq!(result.rounds.last().unwrap().exhausted, 0.0);
+    }
+}
@@ -0,0 +1,279 @@
+//! End-to-end verifiable plurality polls.
+//!
+//! Every vote is recorded on a public bulletin board as a commitment, a SHA-256 hash of the poll
+//! id, the choice and a random nonce. Board entries are hash-chained, so an entry cannot be
+//! dropped or altered without changing every later hash. Voters receive the opening of their own
+//! commitment as a receipt. When the poll closes the bot publishes a [`Proof`]: the board, the
+//! openings of every live commitment in an order unrelated to voting, and the tally. Anyone can
+//! run [`verify`] on it offline.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+fn sha256(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // Length-prefix every part so that no two different inputs hash the same bytes.
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+pub fn new_nonce() -> String {
+    let mut nonce = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    hex::encode(nonce)
+}
+
+/// Hash committing to `choice` in `poll_id`, where `None` is an abstention.
+pub fn commit(poll_id: &str, choice: Option<&str>, nonce: &str) -> String {
+    let choice = match choice {
+        Some(choice) => format!("choice:{}", choice),
+        None => "abstain".to_string(),
+    };
+    sha256(&[poll_id.as_bytes(), choice.as_bytes(), nonce.as_bytes()])
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "commitment", rename_all = "kebab-case")]
+pub enum BoardAction {
+    Cast(String),
+    /// A voter changed or withdrew the vote behind this commitment.
+    Revoke(String),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BoardEntry {
+    #[serde(flatten)]
+    pub action: BoardAction,
+    pub hash: String,
+}
+
+fn entry_hash(previous: &str, action: &BoardAction) -> String {
+    let (kind, commitment) = match action {
+        BoardAction::Cast(c) => ("cast", c),
+        BoardAction::Revoke(c) => ("revoke", c),
+    };
+    sha256(&[previous.as_bytes(), kind.as_bytes(), commitment.as_bytes()])
+}
+
+/// Append-only, hash-chained log of commitments for one poll.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Board {
+    pub poll_id: String,
+    pub entries: Vec<BoardEntry>,
+}
+
+impl Board {
+    pub fn new(poll_id: &str) -> Self {
+        Board {
+            poll_id: poll_id.to_string(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// The hash every later entry depends on; the poll id's hash while the board is empty.
+    pub fn head(&self) -> String {
+        match self.entries.last() {
+            Some(entry) => entry.hash.clone(),
+            None => sha256(&[self.poll_id.as_bytes()]),
+        }
+    }
+
+    pub fn append(&mut self, action: BoardAction) {
+        let hash = entry_hash(&self.head(), &action);
+        self.entries.push(BoardEntry { action, hash });
+    }
+
+    /// Check the hash chain and return the commitments cast and not since revoked.
+    pub fn live_commitments(&self) -> Result<BTreeSet<String>, String> {
+        let mut previous = sha256(&[self.poll_id.as_bytes()]);
+        let mut live = BTreeSet::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry_hash(&previous, &entry.action) != entry.hash {
+                return Err(format!(
+                    "board entry {} does not chain from the one before",
+                    i
+                ));
+            }
+            match &entry.action {
+                BoardAction::Cast(c) => {
+                    if !live.insert(c.clone()) {
+                        return Err(format!("board entry {} casts {} twice", i, c));
+                    }
+                }
+                BoardAction::Revoke(c) => {
+                    if !live.remove(c) {
+                        return Err(format!("board entry {} revokes unknown {}", i, c));
+                    }
+                }
+            }
+            previous = entry.hash.clone();
+        }
+        Ok(live)
+    }
+}
+
+/// What a commitment hides, given to its voter as a receipt and published at close.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Opening {
+    pub commitment: String,
+    pub choice: Option<String>,
+    pub nonce: String,
+}
+
+impl Opening {
+    pub fn new(poll_id: &str, choice: Option<String>) -> Self {
+        let nonce = new_nonce();
+        Opening {
+            commitment: commit(poll_id, choice.as_deref(), &nonce),
+            choice,
+            nonce,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Proof {
+    pub board: Board,
+    pub options: Vec<String>,
+    pub openings: Vec<Opening>,
+    pub tally: Vec<(String, usize)>,
+    pub abstentions: usize,
+}
+
+impl Proof {
+    /// Build the proof for `board`, given the openings of its live commitments.
+    pub fn new(board: &Board, options: &[String], openings: &[&Opening]) -> Self {
+        let mut openings: Vec<Opening> = openings.iter().map(|o| (*o).clone()).collect();
+        // Sorting by commitment hash hides the order in which votes were cast.
+        openings.sort_by(|a, b| a.commitment.cmp(&b.commitment));
+        let (tally, abstentions) = count(options, &openings);
+        Proof {
+            board: board.clone(),
+            options: options.to_vec(),
+            openings,
+            tally,
+            abstentions,
+        }
+    }
+}
+
+fn count(options: &[String], openings: &[Opening]) -> (Vec<(String, usize)>, usize) {
+    let mut tally: Vec<(String, usize)> = options.iter().map(|o| (o.clone(), 0)).collect();
+    let mut abstentions = 0;
+    for opening in openings {
+        match &opening.choice {
+            Some(choice) => {
+                if let Some((_, n)) = tally.iter_mut().find(|(o, _)| o == choice) {
+                    *n += 1;
+                }
+            }
+            None => abstentions += 1,
+        }
+    }
+    (tally, abstentions)
+}
+
+/// Check that the published openings are exactly the live commitments on the board, that each
+/// opens correctly to one of the poll's options, and that they add up to the published tally.
+pub fn verify(proof: &Proof) -> Result<(), String> {
+    let live = proof.board.live_commitments()?;
+
+    let mut opened = BTreeMap::new();
+    for opening in &proof.openings {
+        let expected = commit(
+            &proof.board.poll_id,
+            opening.choice.as_deref(),
+            &opening.nonce,
+        );
+        if expected != opening.commitment {
+            return Err(format!("opening of {} is wrong", opening.commitment));
+        }
+        if let Some(choice) = &opening.choice {
+            if !proof.options.contains(choice) {
+                return Err(format!("{} opens to unknown option {}", expected, choice));
+            }
+        }
+        if opened.insert(opening.commitment.clone(), opening).is_some() {
+            return Err(format!("{} is opened twice", opening.commitment));
+        }
+    }
+
+    if let Some(missing) = live.iter().find(|c| !opened.contains_key(*c)) {
+        return Err(format!("live commitment {} is not opened", missing));
+    }
+    if let Some(extra) = opened.keys().find(|c| !live.contains(*c)) {
+        return Err(format!("{} is opened but not live on the board", extra));
+    }
+
+    if count(&proof.options, &proof.openings) != (proof.tally.clone(), proof.abstentions) {
+        return Err("the published tally does not match the openings".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proof() -> Proof {
+        let options = vec!["Yes".to_string(), "No".to_string()];
+        let mut board = Board::new("motion");
+        let first = Opening::new("motion", Some("Yes".to_string()));
+        let changed = Opening::new("motion", Some("No".to_string()));
+        let second = Opening::new("motion", Some("Yes".to_string()));
+        let abstain = Opening::new("motion", None);
+        board.append(BoardAction::Cast(first.commitment.clone()));
+        board.append(BoardAction::Cast(changed.commitment.clone()));
+        board.append(BoardAction::Revoke(changed.commitment.clone()));
+        board.append(BoardAction::Cast(second.commitment.clone()));
+        board.append(BoardAction::Cast(abstain.commitment.clone()));
+        Proof::new(&board, &options, &[&first, &second, &abstain])
+    }
+
+    #[test]
+    fn honest_proof_verifies() {
+        let proof = proof();
+        assert_eq!(
+            proof.tally,
+            vec![("Yes".to_string(), 2), ("No".to_string(), 0)]
+        );
+        assert_eq!(proof.abstentions, 1);
+        assert!(verify(&proof).is_ok());
+    }
+
+    #[test]
+    fn altered_tally_is_rejected() {
+        let mut proof = proof();
+        proof.tally[1].1 = 1;
+        assert!(verify(&proof).is_err());
+    }
+
+    #[test]
+    fn altered_opening_is_rejected() {
+        let mut proof = proof();
+        let opening = proof
+            .openings
+            .iter_mut()
+            .find(|o| o.choice.is_some())
+            .unwrap();
+        opening.choice = Some("No".to_string());
+        assert!(verify(&proof).is_err());
+    }
+
+    #[test]
+    fn dropped_vote_is_rejected() {
+        let mut proof = proof();
+        proof.openings.pop();
+        assert!(verify(&proof).is_err());
+
+        let mut proof = self::proof();
+        proof.board.entries.remove(0);
+        assert!(verify(&proof).is_err());
+    }
+}